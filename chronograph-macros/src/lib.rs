@@ -2,91 +2,1063 @@
 //!
 //! These macros provide ergonomic ways to interact with chronograph's thread-local spans.
 //! They wrap the thread-local span functionality to make it easier to use in your code.
+//! The record macros also take an explicit span as their first argument, and [start_span!] an explicit chronograph,
+//! for code managing its own spans, such as per-request spans in async code.
+//!
+//! String literal datapoint ids are hashed at compile time, so recording them costs no more than recording a numeric id.
+//!
+//! The record macros take an optional `level = minimal|standard|verbose` first argument,
+//! which compiles the record away when the level is disabled by chronograph's cargo features.
+//!
+//! With chronograph's `disabled` feature, the record macros, [macro@instrument], [span!], [time_block!] and [defer_record!]
+//! compile to nothing but their arguments and blocks, [sampled!] skips its block, and [span_guard!] returns `()`.
+//! The other macros managing the thread-local span are unchanged, since code may depend on the span they return,
+//! but that span is never recorded.
 
 use proc_macro::TokenStream;
-use quote::quote;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, quote_spanned};
+use syn::ext::IdentExt;
 use syn::parse::{Parse, ParseStream, Result};
-use syn::{parse_macro_input, Expr, Token};
+use syn::punctuated::Punctuated;
+use syn::{
+    parse_macro_input, parse_quote, Attribute, Block, Expr, ExprLit, FnArg, Ident, ItemFn, Lit,
+    LitStr, Pat, Signature, Token, Visibility,
+};
+
+/// Whether the `disabled` feature compiles instrumentation away
+const DISABLED: bool = cfg!(feature = "disabled");
+
+/// An optional `level = <tier>,` argument, resolved to a `chronograph::level::Level` variant
+struct LevelArg(Option<Ident>);
+
+impl Parse for LevelArg {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let fork = input.fork();
+        let is_level = fork.parse::<Ident>().is_ok_and(|x| x == "level")
+            && fork.peek(Token![=])
+            && !fork.peek(Token![==]);
+        if !is_level {
+            return Ok(Self(None));
+        }
+        input.parse::<Ident>()?;
+        input.parse::<Token![=]>()?;
+        let tier: Ident = input.parse()?;
+        input.parse::<Token![,]>()?;
+        let variant = match tier.to_string().as_str() {
+            "minimal" => "Minimal",
+            "standard" => "Standard",
+            "verbose" => "Verbose",
+            _ => {
+                return Err(syn::Error::new(
+                    tier.span(),
+                    "expected one of `minimal`, `standard` or `verbose`",
+                ))
+            }
+        };
+        Ok(Self(Some(Ident::new(variant, tier.span()))))
+    }
+}
 
-/// Start a new thread-local span using the global chronograph.
+impl LevelArg {
+    /// Wrap a record so it is compiled away when its level, or all instrumentation, is disabled
+    fn wrap(self, record: TokenStream2) -> TokenStream {
+        if DISABLED {
+            // still type-checked, so the arguments are not reported as unused
+            return quote! {
+                if false {
+                    #record;
+                }
+            }
+            .into();
+        }
+        match self.0 {
+            Some(level) => quote! {
+                if chronograph::level::Level::#level.is_enabled() {
+                    #record;
+                }
+            },
+            None => record,
+        }
+        .into()
+    }
+}
+
+/// A datapoint id, hashed at compile time when it is a string literal rather than on every record
+fn datapoint_id(id: Expr) -> TokenStream2 {
+    match id {
+        Expr::Lit(ExprLit {
+            lit: Lit::Str(name),
+            ..
+        }) => quote! {
+            {
+                const ID: chronograph::schema::DatapointId =
+                    chronograph::schema::DatapointId::from_name(#name);
+                ID
+            }
+        },
+        id => quote!(#id),
+    }
+}
+
+/// Call `record`, a method call such as `.record_instant(id)`, on the given span,
+/// or on the current thread-local span through `chronograph::with_threadlocal_span` when none is given
+fn record_into(span: Option<Expr>, record: TokenStream2) -> TokenStream2 {
+    match span {
+        Some(span) => quote!((#span) #record),
+        None => quote! {
+            chronograph::with_threadlocal_span(|__chronograph_span| {
+                __chronograph_span #record;
+            })
+        },
+    }
+}
+
+/// A single expression after an optional [LevelArg] and an optional span
+struct LeveledExpr {
+    level: LevelArg,
+    span: Option<Expr>,
+    expr: Expr,
+}
+
+impl Parse for LeveledExpr {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let level = input.parse()?;
+        let first = input.parse()?;
+        if input.parse::<Option<Token![,]>>()?.is_none() {
+            return Ok(LeveledExpr {
+                level,
+                span: None,
+                expr: first,
+            });
+        }
+        Ok(LeveledExpr {
+            level,
+            span: Some(first),
+            expr: input.parse()?,
+        })
+    }
+}
+
+/// Start a new thread-local span using the global chronograph,
+/// or start and return a new span from the given chronograph without touching the thread-local span.
 ///
 /// # Example
 /// ```rust
+/// # use chronograph::macros::*;
+/// # let chronograph = chronograph::Chronograph::builder().build();
 /// start_span!();
+/// let mut span = start_span!(chronograph);
 /// ```
 #[proc_macro]
-pub fn start_span(_input: TokenStream) -> TokenStream {
+pub fn start_span(input: TokenStream) -> TokenStream {
+    if !input.is_empty() {
+        let chronograph = parse_macro_input!(input as Expr);
+        return quote! {
+            (#chronograph).start_span()
+        }
+        .into();
+    }
     quote! {
         chronograph::start_threadlocal_span()
     }
     .into()
 }
 
-/// Record an instant datapoint in the current thread-local span.
+/// Start a child of the current thread-local span as the new thread-local span.
+///
+/// The parent becomes the current thread-local span again when the child is ended with [end_span!],
+/// so nested instrumentation composes.
 ///
 /// # Example
 /// ```rust
-/// record_instant!("my_datapoint");
+/// # use chronograph::macros::*;
+/// start_span!();
+/// child_span!();
+/// record_instant!("nested_op");
+/// end_span!(); // the parent is current again
+/// end_span!();
 /// ```
 #[proc_macro]
-pub fn record_instant(input: TokenStream) -> TokenStream {
-    let expr = parse_macro_input!(input as Expr);
+pub fn child_span(_input: TokenStream) -> TokenStream {
     quote! {
-        chronograph::get_threadlocal_span().record_instant(#expr)
+        chronograph::start_threadlocal_child_span()
     }
     .into()
 }
 
-/// Record a unix time datapoint in the current thread-local span.
+/// Record an instant datapoint in the current thread-local span, or in the given span.
 ///
 /// # Example
 /// ```rust
+/// # use chronograph::macros::*;
+/// # let chronograph = chronograph::Chronograph::builder().build();
+/// # let mut span = chronograph.start_span();
+/// record_instant!("my_datapoint");
+/// record_instant!(level = verbose, "my_detailed_datapoint");
+/// record_instant!(span, "my_datapoint");
+/// ```
+#[proc_macro]
+pub fn record_instant(input: TokenStream) -> TokenStream {
+    let LeveledExpr { level, span, expr } = parse_macro_input!(input as LeveledExpr);
+    let id = datapoint_id(expr);
+    level.wrap(record_into(span, quote!(.record_instant(#id))))
+}
+
+/// Record the duration since the previous instant or lap of the current thread-local span, or of the given span,
+/// as a duration datapoint, such as to profile each phase of a loop.
+///
+/// # Example
+/// ```rust
+/// # use chronograph::macros::*;
+/// # let items = [1, 2, 3];
+/// # fn decode(_: u64) {}
+/// # fn store(_: u64) {}
+/// for item in items {
+///     decode(item);
+///     lap!("decode");
+///     store(item);
+///     lap!("store");
+/// }
+/// ```
+#[proc_macro]
+pub fn lap(input: TokenStream) -> TokenStream {
+    let LeveledExpr { level, span, expr } = parse_macro_input!(input as LeveledExpr);
+    let id = datapoint_id(expr);
+    level.wrap(record_into(span, quote!(.record_lap(#id))))
+}
+
+/// Record a unix time datapoint in the current thread-local span, or in the given span.
+///
+/// # Example
+/// ```rust
+/// # use chronograph::macros::*;
+/// # let chronograph = chronograph::Chronograph::builder().build();
+/// # let mut span = chronograph.start_span();
 /// record_unix_time!("timestamp");
+/// record_unix_time!(span, "timestamp");
 /// ```
 #[proc_macro]
 pub fn record_unix_time(input: TokenStream) -> TokenStream {
-    let expr = parse_macro_input!(input as Expr);
-    quote! {
-        chronograph::get_threadlocal_span().record_unix_time(#expr)
-    }
-    .into()
+    let LeveledExpr { level, span, expr } = parse_macro_input!(input as LeveledExpr);
+    let id = datapoint_id(expr);
+    level.wrap(record_into(span, quote!(.record_unix_time(#id))))
 }
 
+/// An id and value after an optional [LevelArg] and an optional span
 struct ValueInput {
+    level: LevelArg,
+    span: Option<Expr>,
     id: Expr,
-    _comma: Token![,],
     value: Expr,
 }
 
 impl Parse for ValueInput {
     fn parse(input: ParseStream) -> Result<Self> {
+        let level = input.parse()?;
+        let first = input.parse()?;
+        input.parse::<Token![,]>()?;
+        let second = input.parse()?;
+        if input.parse::<Option<Token![,]>>()?.is_none() {
+            return Ok(ValueInput {
+                level,
+                span: None,
+                id: first,
+                value: second,
+            });
+        }
         Ok(ValueInput {
-            id: input.parse()?,
-            _comma: input.parse()?,
+            level,
+            span: Some(first),
+            id: second,
             value: input.parse()?,
         })
     }
 }
 
-/// Record a value datapoint in the current thread-local span.
+/// Record a value datapoint in the current thread-local span, or in the given span.
 ///
 /// # Example
 /// ```rust
+/// # use chronograph::macros::*;
+/// # let chronograph = chronograph::Chronograph::builder().build();
+/// # let mut span = chronograph.start_span();
 /// record_value!("count", 42);
+/// record_value!(level = standard, "retries", 3);
+/// record_value!(span, "count", 42);
 /// ```
 #[proc_macro]
 pub fn record_value(input: TokenStream) -> TokenStream {
-    let ValueInput { id, value, .. } = parse_macro_input!(input as ValueInput);
+    let ValueInput {
+        level,
+        span,
+        id,
+        value,
+    } = parse_macro_input!(input as ValueInput);
+    let id = datapoint_id(id);
+    let record = record_into(span, quote!(.record_value(#id, __chronograph_value)));
+    // the value is evaluated before borrowing the span, since it may record into thread-local spans itself
+    level.wrap(quote! {
+        {
+            let __chronograph_value = #value;
+            #record
+        }
+    })
+}
+
+/// A condition and an id after an optional [LevelArg] and an optional span
+struct ConditionalInstant {
+    level: LevelArg,
+    span: Option<Expr>,
+    cond: Expr,
+    id: Expr,
+}
+
+impl Parse for ConditionalInstant {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let level = input.parse()?;
+        let first = input.parse()?;
+        input.parse::<Token![,]>()?;
+        let second = input.parse()?;
+        if input.parse::<Option<Token![,]>>()?.is_none() {
+            return Ok(ConditionalInstant {
+                level,
+                span: None,
+                cond: first,
+                id: second,
+            });
+        }
+        Ok(ConditionalInstant {
+            level,
+            span: Some(first),
+            cond: second,
+            id: input.parse()?,
+        })
+    }
+}
+
+/// A condition, an id and a value after an optional [LevelArg] and an optional span
+struct ConditionalValue {
+    level: LevelArg,
+    span: Option<Expr>,
+    cond: Expr,
+    id: Expr,
+    value: Expr,
+}
+
+impl Parse for ConditionalValue {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let level = input.parse()?;
+        let first = input.parse()?;
+        input.parse::<Token![,]>()?;
+        let second = input.parse()?;
+        input.parse::<Token![,]>()?;
+        let third = input.parse()?;
+        if input.parse::<Option<Token![,]>>()?.is_none() {
+            return Ok(ConditionalValue {
+                level,
+                span: None,
+                cond: first,
+                id: second,
+                value: third,
+            });
+        }
+        Ok(ConditionalValue {
+            level,
+            span: Some(first),
+            cond: second,
+            id: third,
+            value: input.parse()?,
+        })
+    }
+}
+
+/// Record an instant datapoint in the current thread-local span, or in the given span, only when the condition is true,
+/// without accessing the thread-local span otherwise.
+///
+/// # Example
+/// ```rust
+/// # use chronograph::macros::*;
+/// # let chronograph = chronograph::Chronograph::builder().build();
+/// # let mut span = chronograph.start_span();
+/// # let attempt = 1;
+/// record_instant_if!(attempt > 0, "retry_start");
+/// record_instant_if!(span, attempt > 0, "retry_start");
+/// ```
+#[proc_macro]
+pub fn record_instant_if(input: TokenStream) -> TokenStream {
+    let ConditionalInstant {
+        level,
+        span,
+        cond,
+        id,
+    } = parse_macro_input!(input as ConditionalInstant);
+    let id = datapoint_id(id);
+    let record = record_into(span, quote!(.record_instant(#id)));
+    level.wrap(quote! {
+        if #cond {
+            #record;
+        }
+    })
+}
+
+/// Record a value datapoint in the current thread-local span, or in the given span, only when the condition is true,
+/// without evaluating the value or accessing the thread-local span otherwise.
+///
+/// # Example
+/// ```rust
+/// # use chronograph::macros::*;
+/// # let chronograph = chronograph::Chronograph::builder().build();
+/// # let mut span = chronograph.start_span();
+/// # let response: Result<(), &str> = Err("timed out");
+/// # let (cache_miss, key) = (true, "user:42".to_owned());
+/// record_value_if!(response.is_err(), "error_body", format!("{response:?}"));
+/// record_value_if!(level = verbose, cache_miss, "miss_key", key.clone());
+/// record_value_if!(span, cache_miss, "miss_key", key);
+/// ```
+#[proc_macro]
+pub fn record_value_if(input: TokenStream) -> TokenStream {
+    let ConditionalValue {
+        level,
+        span,
+        cond,
+        id,
+        value,
+    } = parse_macro_input!(input as ConditionalValue);
+    let id = datapoint_id(id);
+    let record = record_into(span, quote!(.record_value(#id, __chronograph_value)));
+    level.wrap(quote! {
+        if #cond {
+            // the value is evaluated before borrowing the span, since it may record into thread-local spans itself
+            let __chronograph_value = #value;
+            #record;
+        }
+    })
+}
+
+/// Mark the current thread-local span, or the given span, as failed,
+/// storing the error's `Display` chain of sources as its status message.
+///
+/// The expression is borrowed, so a `Box<dyn Error>` is passed dereferenced.
+///
+/// # Example
+/// ```rust
+/// # use chronograph::macros::*;
+/// # let chronograph = chronograph::Chronograph::builder().build();
+/// # let mut span = chronograph.start_span();
+/// # fn connect() -> Result<(), std::io::Error> {
+/// #     Err(std::io::Error::other("connection refused"))
+/// # }
+/// # let boxed_error: Box<dyn std::error::Error> = "invalid".parse::<u64>().unwrap_err().into();
+/// if let Err(err) = connect() {
+///     record_error!(err);
+///     record_error!(span, err);
+/// }
+/// record_error!(*boxed_error);
+/// ```
+#[proc_macro]
+pub fn record_error(input: TokenStream) -> TokenStream {
+    let LeveledExpr { level, span, expr } = parse_macro_input!(input as LeveledExpr);
+    let record = record_into(span, quote!(.record_error(__chronograph_error)));
+    // the error is evaluated before borrowing the span, since it may record into thread-local spans itself
+    level.wrap(quote! {
+        {
+            let __chronograph_error = &#expr;
+            #record
+        }
+    })
+}
+
+/// An `id => value` pair of [record_values!]
+struct ValuePair {
+    id: Expr,
+    _arrow: Token![=>],
+    value: Expr,
+}
+
+impl Parse for ValuePair {
+    fn parse(input: ParseStream) -> Result<Self> {
+        Ok(ValuePair {
+            id: input.parse()?,
+            _arrow: input.parse()?,
+            value: input.parse()?,
+        })
+    }
+}
+
+struct ValuesInput {
+    level: LevelArg,
+    pairs: Punctuated<ValuePair, Token![,]>,
+}
+
+impl Parse for ValuesInput {
+    fn parse(input: ParseStream) -> Result<Self> {
+        Ok(ValuesInput {
+            level: input.parse()?,
+            pairs: Punctuated::parse_terminated(input)?,
+        })
+    }
+}
+
+/// Record several value datapoints in the current thread-local span, accessing it only once.
+///
+/// # Example
+/// ```rust
+/// # use chronograph::macros::*;
+/// # let (depth, size) = (3, 64);
+/// record_values!("count" => 42, "retries" => 3, "cache_hit" => true);
+/// record_values!(level = verbose, "queue_depth" => depth, "batch_size" => size);
+/// ```
+#[proc_macro]
+pub fn record_values(input: TokenStream) -> TokenStream {
+    let ValuesInput { level, pairs } = parse_macro_input!(input as ValuesInput);
+    let (ids, values): (Vec<_>, Vec<_>) = pairs
+        .into_iter()
+        .map(|ValuePair { id, value, .. }| (datapoint_id(id), value))
+        .unzip();
+    let bindings = (0..values.len())
+        .map(|i| {
+            Ident::new(
+                &format!("__chronograph_value_{i}"),
+                proc_macro2::Span::call_site(),
+            )
+        })
+        .collect::<Vec<_>>();
+    // the values are evaluated before borrowing the span, since they may record into thread-local spans themselves
+    level.wrap(quote! {
+        {
+            #(let #bindings = #values;)*
+            chronograph::with_threadlocal_span(|__chronograph_span| {
+                #(__chronograph_span.record_value(#ids, #bindings);)*
+            });
+        }
+    })
+}
+
+/// Record a duration datapoint in the current thread-local span, or in the given span, from a [std::time::Duration].
+///
+/// # Example
+/// ```rust
+/// # use chronograph::macros::*;
+/// # use std::time::{Duration, Instant};
+/// # let chronograph = chronograph::Chronograph::builder().build();
+/// # let mut span = chronograph.start_span();
+/// # let started = Instant::now();
+/// record_duration!("backoff", Duration::from_millis(50));
+/// record_duration!(level = verbose, "elapsed", started.elapsed());
+/// record_duration!(span, "backoff", Duration::from_millis(50));
+/// ```
+#[proc_macro]
+pub fn record_duration(input: TokenStream) -> TokenStream {
+    let ValueInput {
+        level,
+        span,
+        id,
+        value,
+    } = parse_macro_input!(input as ValueInput);
+    let id = datapoint_id(id);
+    let record = record_into(span, quote!(.record_duration(#id, __chronograph_value)));
+    // the value is evaluated before borrowing the span, since it may record into thread-local spans itself
+    level.wrap(quote! {
+        {
+            let __chronograph_value = #value;
+            #record
+        }
+    })
+}
+
+/// A `NAME = "name"` declaration of [datapoints!]
+struct DatapointDecl {
+    attrs: Vec<Attribute>,
+    vis: Visibility,
+    ident: Ident,
+    _eq: Token![=],
+    name: LitStr,
+}
+
+impl Parse for DatapointDecl {
+    fn parse(input: ParseStream) -> Result<Self> {
+        Ok(DatapointDecl {
+            attrs: input.call(Attribute::parse_outer)?,
+            vis: input.parse()?,
+            ident: input.parse()?,
+            _eq: input.parse()?,
+            name: input.parse()?,
+        })
+    }
+}
+
+/// The input of [datapoints!]: an optional `#![cardinality_limit = N]` followed by declarations
+struct DatapointsInput {
+    cardinality_limit: Option<usize>,
+    decls: Punctuated<DatapointDecl, Token![,]>,
+}
+
+impl Parse for DatapointsInput {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let mut cardinality_limit = None;
+        for attr in input.call(Attribute::parse_inner)? {
+            let value = match &attr.meta {
+                syn::Meta::NameValue(x) if x.path.is_ident("cardinality_limit") => &x.value,
+                _ => {
+                    return Err(syn::Error::new_spanned(
+                        attr,
+                        "expected `#![cardinality_limit = N]`",
+                    ))
+                }
+            };
+            cardinality_limit = match value {
+                Expr::Lit(ExprLit {
+                    lit: Lit::Int(x), ..
+                }) => Some(x.base10_parse()?),
+                _ => return Err(syn::Error::new_spanned(value, "expected an integer limit")),
+            };
+        }
+        Ok(DatapointsInput {
+            cardinality_limit,
+            decls: Punctuated::parse_terminated(input)?,
+        })
+    }
+}
+
+/// Declare datapoint ids as constants, hashed at compile time, along with a `register_datapoints` function
+/// that registers their names in `chronograph::registry`, so batches carry them for reverse lookup.
+///
+/// `register_datapoints` is declared in the module invoking the macro, so the macro can be invoked once per module.
+/// It is `pub(crate)` and not exempt from the `dead_code` lint, so forgetting to call it is reported as a warning
+/// rather than silently leaving the names unresolved. A library exposing its datapoints calls it from its own setup function.
+///
+/// Declaring the same name twice is a compile error. A leading `#![cardinality_limit = N]` also makes declaring
+/// more than `N` names a compile error, checking the budget of `chronograph::registry::set_cardinality_limit`
+/// at build time for the names known up front.
+///
+/// # Example
+/// ```rust
+/// # use chronograph::macros::*;
+/// datapoints! {
+///     #![cardinality_limit = 16]
+///     /// When the operation started
+///     pub MY_OP_START = "my_op_start",
+///     MY_OP_END = "my_op_end",
+/// }
+///
+/// register_datapoints();
+/// record_instant!(MY_OP_START);
+/// ```
+#[proc_macro]
+pub fn datapoints(input: TokenStream) -> TokenStream {
+    let DatapointsInput {
+        cardinality_limit,
+        decls,
+    } = parse_macro_input!(input as DatapointsInput);
+    let mut seen = std::collections::HashSet::new();
+    for decl in &decls {
+        if !seen.insert(decl.name.value()) {
+            return syn::Error::new_spanned(&decl.name, "datapoint name declared twice")
+                .to_compile_error()
+                .into();
+        }
+    }
+    if let Some(limit) = cardinality_limit {
+        if let Some(decl) = decls.iter().nth(limit) {
+            return syn::Error::new_spanned(
+                &decl.name,
+                format!(
+                    "{} datapoint names exceed the cardinality limit of {limit}",
+                    decls.len()
+                ),
+            )
+            .to_compile_error()
+            .into();
+        }
+    }
+    let consts = decls.iter().map(|x| {
+        let DatapointDecl {
+            attrs,
+            vis,
+            ident,
+            name,
+            ..
+        } = x;
+        quote! {
+            #(#attrs)*
+            #vis const #ident: chronograph::schema::DatapointId =
+                chronograph::schema::DatapointId::from_name(#name);
+        }
+    });
+    let names = decls.iter().map(|x| &x.name);
+    // spanned to the invocation, since lints such as `dead_code` skip items spanned to the macro itself
+    let span = decls
+        .first()
+        .map_or_else(proc_macro2::Span::call_site, |x| x.ident.span());
+    let register = quote_spanned! {span=>
+        /// Register the names of the datapoints declared in this module with `chronograph::registry`
+        pub(crate) fn register_datapoints() {
+            #(chronograph::registry::register_name(#names);)*
+        }
+    };
     quote! {
-        chronograph::get_threadlocal_span().record_value(#id, #value)
+        #(#consts)*
+        #register
     }
     .into()
 }
 
-/// Take the current thread-local span, leaving None in its place.
+/// The arguments of [macro@instrument], in any order
+#[derive(Default)]
+struct InstrumentArgs {
+    name: Option<LitStr>,
+    args: Vec<Ident>,
+    err: bool,
+}
+
+impl Parse for InstrumentArgs {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let mut result = InstrumentArgs::default();
+        while !input.is_empty() {
+            let key: Ident = input.parse()?;
+            if key == "name" {
+                input.parse::<Token![=]>()?;
+                result.name = Some(input.parse()?);
+            } else if key == "args" {
+                let content;
+                syn::parenthesized!(content in input);
+                let args = Punctuated::<Ident, Token![,]>::parse_terminated(&content)?;
+                result.args.extend(args);
+            } else if key == "err" {
+                result.err = true;
+            } else {
+                return Err(syn::Error::new(
+                    key.span(),
+                    "expected `name = \"...\"`, `args(...)` or `err`",
+                ));
+            }
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// Whether the function has an argument bound to `ident`
+fn has_arg(sig: &Signature, ident: &Ident) -> bool {
+    sig.inputs.iter().any(|x| match x {
+        FnArg::Typed(x) => matches!(&*x.pat, Pat::Ident(pat) if pat.ident == *ident),
+        FnArg::Receiver(_) => false,
+    })
+}
+
+/// Run the function in its own thread-local span, named after the function.
+///
+/// The span is a child of the current thread-local span, or a new span from the global chronograph when there is none.
+/// It records an `enter` instant when the function is called and an `exit` instant when it returns,
+/// and the previous thread-local span is restored afterwards.
+///
+/// `args(...)` records the listed arguments as datapoints named after them when the span starts,
+/// converted with their `Into<RecordValue>` impl when they have one, and with their `Display` impl otherwise.
+///
+/// `err` marks the span as failed when the function returns an `Err`, with the error's `Display` as the status message,
+/// so failures can be told apart from successes, such as to compare their latencies.
+///
+/// An `async fn` keeps its span across await points, as the thread-local span whenever its future is polled,
+/// see `chronograph::future`. Its `enter` instant is recorded when the future is first polled,
+/// and its `exit` instant when the future completes or is dropped.
+///
+/// # Example
+/// ```rust
+/// # use chronograph::macros::*;
+/// # use std::num::ParseIntError;
+/// # async fn do_io() {}
+/// #[instrument]
+/// fn handle_request(count: u64) {
+///     record_value!("count", count);
+/// }
+///
+/// #[instrument(name = "custom_name")]
+/// fn named() {}
+///
+/// #[instrument(args(user_id, path))]
+/// fn handle(user_id: u64, path: &str, body: &[u8]) {}
+///
+/// #[instrument(err)]
+/// fn parse(input: &str) -> Result<u64, ParseIntError> {
+///     input.parse()
+/// }
+///
+/// #[instrument]
+/// async fn fetch() {
+///     record_instant!("before_await");
+///     do_io().await;
+///     record_instant!("after_await");
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn instrument(args: TokenStream, input: TokenStream) -> TokenStream {
+    let InstrumentArgs { name, args, err } = parse_macro_input!(args as InstrumentArgs);
+    let mut item = parse_macro_input!(input as ItemFn);
+    if let Some(arg) = args.iter().find(|x| !has_arg(&item.sig, x)) {
+        return syn::Error::new(arg.span(), format!("no argument named `{arg}`"))
+            .to_compile_error()
+            .into();
+    }
+    if DISABLED {
+        return quote!(#item).into();
+    }
+    let name = name.unwrap_or_else(|| {
+        let ident = &item.sig.ident;
+        LitStr::new(&ident.unraw().to_string(), ident.span())
+    });
+    let enter = datapoint_id(parse_quote!("enter"));
+    let exit = datapoint_id(parse_quote!("exit"));
+    let record_args = args.iter().map(|arg| {
+        let id = datapoint_id(Expr::Lit(ExprLit {
+            attrs: Vec::new(),
+            lit: Lit::Str(LitStr::new(&arg.unraw().to_string(), arg.span())),
+        }));
+        quote! {
+            .record_lazy(#id, || {
+                use chronograph::macro_support::{DisplayArgValue as _, IntoArgValue as _};
+                (&chronograph::macro_support::ArgValue(&#arg)).record_value()
+            })
+        }
+    });
+    let block = &item.block;
+    let block: Block = match (err, item.sig.asyncness.is_some()) {
+        (false, _) => parse_quote!(#block),
+        // the body runs in a closure or future of its own, so returns and `?` still produce the result
+        (true, is_async) => {
+            let result = match is_async {
+                true => quote!((async move #block).await),
+                false => quote!((move || #block)()),
+            };
+            parse_quote! {{
+                #[allow(clippy::redundant_closure_call)]
+                chronograph::macro_support::record_result(#result)
+            }}
+        }
+    };
+    item.block = if item.sig.asyncness.is_some() {
+        // the thread-local span can't be held across await points, so the body runs as a future in its own span
+        parse_quote! {{
+            let mut __chronograph_span = chronograph::new_threadlocal_child_span();
+            __chronograph_span
+                .set_name(#name)
+                .record_instant(#enter)
+                #(#record_args)*
+                .on_drop(|span: &mut chronograph::Span| {
+                    span.record_instant(#exit);
+                });
+            chronograph::future::SpanFutureExt::in_span(async move #block, __chronograph_span).await
+        }}
+    } else {
+        parse_quote! {{
+            let __chronograph_guard = {
+                let mut __chronograph_span = chronograph::new_threadlocal_child_span();
+                __chronograph_span
+                    .set_name(#name)
+                    .record_instant(#enter)
+                    #(#record_args)*;
+                chronograph::ThreadLocalSpanGuard::enter(__chronograph_span).with_exit_instant(#exit)
+            };
+            #block
+        }}
+    };
+    quote!(#item).into()
+}
+
+/// Run a block in its own thread-local span, which ends when the block exits, including through `return`, `?` or a panic.
+///
+/// The span is a child of the current thread-local span, or a new span from the global chronograph when there is none,
+/// and the previous thread-local span is restored afterwards. The macro evaluates to the value of the block.
 ///
 /// # Example
 /// ```rust
+/// # use chronograph::macros::*;
+/// # fn load() -> Result<Vec<u64>, std::io::Error> {
+/// #     Ok(vec![1, 2, 3])
+/// # }
+/// let total = span! {
+///     record_instant!("load_start");
+///     let items = load()?;
+///     record_value!("count", items.len());
+///     items.iter().sum::<u64>()
+/// };
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[proc_macro]
+pub fn span(input: TokenStream) -> TokenStream {
+    let input = TokenStream2::from(input);
+    if DISABLED {
+        return quote!({ #input }).into();
+    }
+    quote! {
+        {
+            let __chronograph_guard =
+                chronograph::ThreadLocalSpanGuard::enter(chronograph::new_threadlocal_child_span());
+            { #input }
+        }
+    }
+    .into()
+}
+
+/// Run a block only when there is a current thread-local span and it is sampled,
+/// so expensive capture code is skipped for spans that will not be recorded.
+///
+/// # Example
+/// ```rust
+/// # use chronograph::macros::*;
+/// # let request = vec!["GET", "/"];
+/// sampled! {
+///     record_value!("request_dump", format!("{request:#?}"));
+/// }
+/// ```
+#[proc_macro]
+pub fn sampled(input: TokenStream) -> TokenStream {
+    let input = TokenStream2::from(input);
+    let condition = match DISABLED {
+        true => quote!(false),
+        false => quote!(chronograph::is_threadlocal_span_sampled()),
+    };
+    quote! {
+        if #condition {
+            #input
+        }
+    }
+    .into()
+}
+
+/// Start a thread-local span, returning a guard that ends it when the guard goes out of scope,
+/// including on `return`, `?` or a panic.
+///
+/// Like [span!], the span is a child of the current thread-local span, or a new span from the global chronograph
+/// when there is none, and the previous thread-local span is restored when it ends.
+///
+/// # Example
+/// ```rust
+/// # use chronograph::macros::*;
+/// # type Error = std::io::Error;
+/// # fn load() -> Result<(), Error> {
+/// #     Ok(())
+/// # }
+/// fn handle() -> Result<(), Error> {
+///     let _guard = span_guard!();
+///     record_instant!("validated");
+///     load()?;
+///     Ok(())
+/// }
+/// ```
+#[proc_macro]
+pub fn span_guard(_input: TokenStream) -> TokenStream {
+    if DISABLED {
+        return quote!(()).into();
+    }
+    quote! {
+        chronograph::ThreadLocalSpanGuard::enter(chronograph::new_threadlocal_child_span())
+    }
+    .into()
+}
+
+/// Record an instant datapoint in the current thread-local span when the enclosing scope exits,
+/// including through `return`, `?` or a panic, such as to measure how long cleanup takes.
+///
+/// Deferred instants of the same scope are recorded in reverse order, after the scope's locals declared later are dropped.
+///
+/// # Example
+/// ```rust
+/// # use chronograph::macros::*;
+/// # struct Pool;
+/// # impl Pool {
+/// #     fn get(&self) -> Vec<u8> {
+/// #         Vec::new()
+/// #     }
+/// # }
+/// # let pool = Pool;
+/// {
+///     defer_record!("cleanup_done");
+///     let connection = pool.get();
+///     record_instant!("query_done");
+/// } // `connection` is returned to the pool, then "cleanup_done" is recorded
+/// ```
+#[proc_macro]
+pub fn defer_record(input: TokenStream) -> TokenStream {
+    let id = datapoint_id(parse_macro_input!(input as Expr));
+    if DISABLED {
+        return quote! {
+            if false {
+                let _ = #id;
+            }
+        }
+        .into();
+    }
+    quote! {
+        let __chronograph_deferred = chronograph::defer_threadlocal_instant(#id);
+    }
+    .into()
+}
+
+struct TimeBlockInput {
+    id: Expr,
+    _comma: Token![,],
+    block: Block,
+}
+
+impl Parse for TimeBlockInput {
+    fn parse(input: ParseStream) -> Result<Self> {
+        Ok(TimeBlockInput {
+            id: input.parse()?,
+            _comma: input.parse()?,
+            block: input.parse()?,
+        })
+    }
+}
+
+/// Record a start and end instant with the same datapoint id around a block in the current thread-local span.
+///
+/// The end instant is recorded however the block exits, including through `return`, `?` or a panic.
+/// The macro evaluates to the value of the block.
+///
+/// # Example
+/// ```rust
+/// # use chronograph::macros::*;
+/// # fn run_query() -> Result<Vec<u64>, std::io::Error> {
+/// #     Ok(vec![])
+/// # }
+/// let rows = time_block!("query", {
+///     run_query()?
+/// });
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[proc_macro]
+pub fn time_block(input: TokenStream) -> TokenStream {
+    let TimeBlockInput { id, block, .. } = parse_macro_input!(input as TimeBlockInput);
+    let id = datapoint_id(id);
+    let stmts = block.stmts;
+    if DISABLED {
+        return quote! {
+            {
+                if false {
+                    let _ = #id;
+                }
+                #(#stmts)*
+            }
+        }
+        .into();
+    }
+    quote! {
+        {
+            let __chronograph_segment = chronograph::enter_threadlocal_segment(#id);
+            #(#stmts)*
+        }
+    }
+    .into()
+}
+
+/// Take the current thread-local span, leaving its parent, if any, as the current thread-local span.
+///
+/// # Example
+/// ```rust
+/// # use chronograph::macros::*;
 /// let span = take_span!();
 /// ```
 #[proc_macro]
@@ -97,10 +1069,35 @@ pub fn take_span(_input: TokenStream) -> TokenStream {
     .into()
 }
 
+/// Set the current thread-local span, such as one taken with [take_span!] on another thread.
+///
+/// # Example
+/// ```rust
+/// # use chronograph::macros::*;
+/// start_span!();
+/// let span = take_span!().unwrap();
+/// std::thread::spawn(move || {
+///     set_span!(span);
+///     record_instant!("on_worker");
+///     end_span!();
+/// });
+/// ```
+#[proc_macro]
+pub fn set_span(input: TokenStream) -> TokenStream {
+    let span = parse_macro_input!(input as Expr);
+    quote! {
+        chronograph::set_threadlocal_span(#span)
+    }
+    .into()
+}
+
 /// End the current thread-local span, dropping it from memory.
 ///
+/// The parent of a span started with [child_span!] becomes the current thread-local span again.
+///
 /// # Example
 /// ```rust
+/// # use chronograph::macros::*;
 /// end_span!();
 /// ```
 #[proc_macro]
@@ -0,0 +1,119 @@
+use std::{
+    io,
+    net::{SocketAddr, ToSocketAddrs},
+    time::Duration,
+};
+
+use chronograph::{
+    json::{spans_json, string_json, JsonResponse, JsonServer},
+    registry,
+};
+
+use crate::{BatchIndex, SpanQuery};
+
+/// Serves queries over a [BatchIndex] from a dedicated thread until it is dropped
+#[derive(Debug)]
+pub struct QueryServer {
+    server: JsonServer,
+}
+
+impl QueryServer {
+    /// Bind to the given address and start serving queries, refreshing the index before each request
+    pub fn bind(mut index: BatchIndex, addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let server = JsonServer::bind(addr, "chronograph query server", move |path, query| {
+            Some(match index.refresh() {
+                Ok(_) => route(&index, path, query),
+                Err(err) => Err((500, format!("could not index batch files: {err}"))),
+            })
+        })?;
+        Ok(Self { server })
+    }
+
+    /// The address the server is listening on
+    pub fn local_addr(&self) -> SocketAddr {
+        self.server.local_addr()
+    }
+}
+
+fn route(index: &BatchIndex, path: &str, query: &str) -> JsonResponse {
+    match path {
+        "/" => Ok(format!(
+            "{{\"files\":{},\"spans\":{}}}",
+            index.file_count(),
+            index.span_count()
+        )),
+        "/names" => Ok(format!(
+            "[{}]",
+            index
+                .names()
+                .into_iter()
+                .map(|x| match registry::resolve_name(x) {
+                    Some(name) => string_json(&name),
+                    None => x.value.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(",")
+        )),
+        "/spans" => {
+            let query = parse_query(query).map_err(|x| (400, x))?;
+            Ok(spans_json(index.query(&query)))
+        }
+        _ => match path.strip_prefix("/traces/") {
+            Some(trace_id) => {
+                let trace_id = u128::from_str_radix(trace_id, 16)
+                    .map_err(|_| (400, format!("invalid trace id: {trace_id}")))?;
+                Ok(spans_json(index.trace(trace_id)))
+            }
+            None => Err((404, format!("unknown path: {path}"))),
+        },
+    }
+}
+
+fn parse_query(query: &str) -> Result<SpanQuery, String> {
+    let mut span_query = SpanQuery::default();
+    let (mut from, mut to) = (None, None);
+    for (key, value) in query
+        .split('&')
+        .filter(|x| !x.is_empty())
+        .map(|x| x.split_once('=').unwrap_or((x, "")))
+    {
+        let value = percent_decode(value);
+        let invalid = |_| format!("invalid value for {key}: {value}");
+        match key {
+            "name" => span_query = span_query.with_name(value.as_str()),
+            "from" => from = Some(value.parse().map_err(invalid)?),
+            "to" => to = Some(value.parse().map_err(invalid)?),
+            "min_duration" => {
+                span_query = span_query
+                    .with_min_duration(Duration::from_nanos(value.parse().map_err(invalid)?))
+            }
+            "limit" => span_query = span_query.with_limit(value.parse().map_err(invalid)?),
+            _ => return Err(format!("unknown query parameter: {key}")),
+        }
+    }
+    Ok(span_query.with_time_range(from, to))
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or_default();
+                match u8::from_str_radix(hex, 16) {
+                    Ok(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    Err(_) => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
@@ -0,0 +1,186 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use chronograph::{
+    registry,
+    schema::{BatchEncoding, DatapointId, SpanBatch, SpanData},
+};
+
+/// An in-memory index of every span in a directory of batch files
+#[derive(Debug)]
+pub struct BatchIndex {
+    dir: PathBuf,
+    encoding: BatchEncoding,
+    indexed: HashSet<PathBuf>,
+    /// Files that could not be decoded, by their length, so they are retried only once they change
+    failed: HashMap<PathBuf, u64>,
+    spans: Vec<SpanData>,
+}
+
+/// Filters spans in a [BatchIndex]
+#[derive(Debug, Clone)]
+pub struct SpanQuery {
+    name: Option<DatapointId>,
+    from_unix_nanos: Option<i64>,
+    to_unix_nanos: Option<i64>,
+    min_duration: Option<Duration>,
+    limit: usize,
+}
+
+impl Default for SpanQuery {
+    fn default() -> Self {
+        Self {
+            name: None,
+            from_unix_nanos: None,
+            to_unix_nanos: None,
+            min_duration: None,
+            limit: 100,
+        }
+    }
+}
+
+impl SpanQuery {
+    /// Only match spans with the given name
+    pub fn with_name(mut self, name: impl Into<DatapointId>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Only match spans that started within `[from, to)`, in unix nanoseconds
+    pub fn with_time_range(
+        mut self,
+        from_unix_nanos: Option<i64>,
+        to_unix_nanos: Option<i64>,
+    ) -> Self {
+        self.from_unix_nanos = from_unix_nanos;
+        self.to_unix_nanos = to_unix_nanos;
+        self
+    }
+
+    /// Only match spans lasting at least the given duration
+    pub fn with_min_duration(mut self, min_duration: Duration) -> Self {
+        self.min_duration = Some(min_duration);
+        self
+    }
+
+    /// Return at most this many spans, defaults to 100
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    fn matches(&self, span: &SpanData) -> bool {
+        self.name.is_none_or(|x| span.name == Some(x))
+            && self
+                .from_unix_nanos
+                .is_none_or(|x| span.start_unix_time >= x)
+            && self.to_unix_nanos.is_none_or(|x| span.start_unix_time < x)
+            && self.min_duration.is_none_or(|x| span.duration() >= x)
+    }
+}
+
+impl BatchIndex {
+    /// Create an empty index of the given directory, where every file is a batch in the given encoding
+    pub fn new(dir: impl AsRef<Path>, encoding: BatchEncoding) -> Self {
+        Self {
+            dir: dir.as_ref().to_owned(),
+            encoding,
+            indexed: HashSet::new(),
+            failed: HashMap::new(),
+            spans: Vec::new(),
+        }
+    }
+
+    /// Index files added to the directory since the last refresh, returning how many were indexed.
+    ///
+    /// Files that can't be read or decoded, such as ones still being written, are skipped until their length changes,
+    /// and entries whose metadata can't be read are skipped, so only failing to list the directory is an error.
+    pub fn refresh(&mut self) -> io::Result<usize> {
+        let mut count = 0;
+        for entry in fs::read_dir(&self.dir)? {
+            let Ok(entry) = entry else {
+                continue;
+            };
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            let path = entry.path();
+            let len = metadata.len();
+            if !metadata.is_file()
+                || self.indexed.contains(&path)
+                || self.failed.get(&path) == Some(&len)
+            {
+                continue;
+            }
+            let decoded = fs::read(&path)
+                .ok()
+                .and_then(|bytes| SpanBatch::decode(&bytes, self.encoding).ok());
+            match decoded {
+                Some(batch) => {
+                    self.failed.remove(&path);
+                    self.indexed.insert(path);
+                    self.add_batch(batch);
+                    count += 1;
+                }
+                None => {
+                    self.failed.insert(path, len);
+                }
+            }
+        }
+        Ok(count)
+    }
+
+    /// Index a batch directly, registering its dictionary so span names can be resolved
+    pub fn add_batch(&mut self, batch: SpanBatch) {
+        for entry in batch.dictionary.iter() {
+            registry::register_name(&entry.name);
+        }
+        self.spans.extend(batch.spans);
+    }
+
+    /// The number of batch files indexed
+    pub fn file_count(&self) -> usize {
+        self.indexed.len()
+    }
+
+    /// The number of spans indexed
+    pub fn span_count(&self) -> usize {
+        self.spans.len()
+    }
+
+    /// Spans matching the query, most recent first
+    pub fn query(&self, query: &SpanQuery) -> Vec<&SpanData> {
+        let mut spans: Vec<&SpanData> = self.spans.iter().filter(|x| query.matches(x)).collect();
+        spans.sort_by_key(|x| std::cmp::Reverse(x.start_unix_time));
+        spans.truncate(query.limit);
+        spans
+    }
+
+    /// Every span of a trace, in start order
+    pub fn trace(&self, trace_id: u128) -> Vec<&SpanData> {
+        let mut spans: Vec<&SpanData> = self
+            .spans
+            .iter()
+            .filter(|x| x.trace_id == trace_id)
+            .collect();
+        spans.sort_by_key(|x| x.start_unix_time);
+        spans
+    }
+
+    /// The distinct names of indexed spans
+    pub fn names(&self) -> Vec<DatapointId> {
+        let mut names: Vec<DatapointId> = self
+            .spans
+            .iter()
+            .filter_map(|x| x.name)
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        names.sort_by_key(|x| x.value);
+        names
+    }
+}
@@ -0,0 +1,46 @@
+//! A minimal self-hosted trace browser, serving queries over a directory of recorded span batch files.
+//!
+//! Every file in the directory holds one [chronograph::schema::SpanBatch], encoded with
+//! [chronograph::schema::SpanBatch::encode]. New files are indexed as they appear, and names from batch
+//! dictionaries are registered so spans are served with their names resolved.
+//!
+//! The [QueryServer] serves the following paths as JSON:
+//! - `/`: the number of indexed files and spans
+//! - `/names`: the names of all indexed spans
+//! - `/spans`: spans matching the query parameters `name`, `from` and `to` (start unix time in nanoseconds),
+//!   `min_duration` (nanoseconds), and `limit`, most recent first
+//! - `/traces/<trace_id>`: every span of the trace with the given hex id, in start order
+//!
+//! # Example
+//! ```rust
+//! use std::time::Duration;
+//! use chronograph::schema::{BatchEncoding, SpanBatch, SpanData};
+//! use chronograph_server::{BatchIndex, SpanQuery};
+//!
+//! let dir = std::env::temp_dir().join("chronograph-server-doctest");
+//! std::fs::create_dir_all(&dir).unwrap();
+//! let batch = SpanBatch {
+//!     spans: vec![SpanData {
+//!         name: Some("checkout".into()),
+//!         start_instant: 0,
+//!         end_instant: 5_000_000,
+//!         ..Default::default()
+//!     }],
+//!     metadata: Default::default(),
+//!     dictionary: Vec::new(),
+//! };
+//! std::fs::write(dir.join("0.batch"), batch.encode(BatchEncoding::Standard)).unwrap();
+//!
+//! let mut index = BatchIndex::new(&dir, BatchEncoding::Standard);
+//! index.refresh().unwrap();
+//! let query = SpanQuery::default()
+//!     .with_name("checkout")
+//!     .with_min_duration(Duration::from_millis(1));
+//! assert_eq!(index.query(&query).len(), 1);
+//! ```
+
+mod http;
+mod index;
+
+pub use http::QueryServer;
+pub use index::{BatchIndex, SpanQuery};
@@ -0,0 +1,59 @@
+use std::process::ExitCode;
+
+use chronograph::schema::BatchEncoding;
+use chronograph_server::{BatchIndex, QueryServer};
+
+const USAGE: &str = "usage: chronograph-server <batch dir> [--addr <host:port>] [--encoding standard|delta-instants|packed-repeats]";
+
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1);
+    let mut dir = None;
+    let mut addr = "127.0.0.1:9899".to_owned();
+    let mut encoding = BatchEncoding::Standard;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--addr" => match args.next() {
+                Some(x) => addr = x,
+                None => return usage(),
+            },
+            "--encoding" => match args.next().as_deref() {
+                Some("standard") => encoding = BatchEncoding::Standard,
+                Some("delta-instants") => encoding = BatchEncoding::DeltaInstants,
+                Some("packed-repeats") => encoding = BatchEncoding::PackedRepeats,
+                _ => return usage(),
+            },
+            _ if dir.is_none() && !arg.starts_with("--") => dir = Some(arg),
+            _ => return usage(),
+        }
+    }
+    let Some(dir) = dir else {
+        return usage();
+    };
+
+    let mut index = BatchIndex::new(&dir, encoding);
+    if let Err(err) = index.refresh() {
+        eprintln!("could not index {dir}: {err}");
+        return ExitCode::FAILURE;
+    }
+    println!(
+        "indexed {} spans from {} files in {dir}",
+        index.span_count(),
+        index.file_count()
+    );
+    let server = match QueryServer::bind(index, &addr) {
+        Ok(server) => server,
+        Err(err) => {
+            eprintln!("could not bind {addr}: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    println!("serving queries on http://{}", server.local_addr());
+    loop {
+        std::thread::park();
+    }
+}
+
+fn usage() -> ExitCode {
+    eprintln!("{USAGE}");
+    ExitCode::FAILURE
+}
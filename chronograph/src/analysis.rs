@@ -0,0 +1,116 @@
+//! Analysis of recorded spans, such as self times and error rates.
+//!
+//! # Example
+//! ```rust
+//! use chronograph::analysis::self_times;
+//! use chronograph::schema::SpanData;
+//!
+//! let span = |span_id, parent_span_id, start_instant, end_instant| SpanData {
+//!     span_id,
+//!     parent_span_id,
+//!     start_instant,
+//!     end_instant,
+//!     ..Default::default()
+//! };
+//! let trace = vec![
+//!     span(0, None, 0, 100),
+//!     span(1, Some(0), 10, 40),
+//!     span(2, Some(0), 50, 60),
+//! ];
+//! let times = self_times(&trace);
+//! assert_eq!(times[0].self_time, 60);
+//! assert_eq!(times[1].self_time, 30);
+//! ```
+
+use std::collections::HashMap;
+
+use crate::schema::{Outcome, SpanData};
+
+/// Inclusive and exclusive time of a single span, in nanoseconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SelfTime {
+    pub span_id: u64,
+    /// Total duration of the span
+    pub total_time: u64,
+    /// Summed duration of the span's direct children
+    pub child_time: u64,
+    /// Total duration minus child duration
+    pub self_time: u64,
+}
+
+/// Compute the exclusive (self) time of every span in a trace, in the same order as the given spans.
+///
+/// Children whose parent is not in the trace are ignored.
+/// Self time saturates at zero when concurrent children overlap their parent.
+pub fn self_times(trace: &[SpanData]) -> Vec<SelfTime> {
+    let mut child_times: HashMap<u64, u64> = HashMap::with_capacity(trace.len());
+    for span in trace {
+        if let Some(parent_id) = span.parent_span_id {
+            *child_times.entry(parent_id).or_default() += duration(span);
+        }
+    }
+    trace
+        .iter()
+        .map(|span| {
+            let total_time = duration(span);
+            let child_time = child_times.get(&span.span_id).copied().unwrap_or_default();
+            SelfTime {
+                span_id: span.span_id,
+                total_time,
+                child_time,
+                self_time: total_time.saturating_sub(child_time),
+            }
+        })
+        .collect()
+}
+
+/// The number of spans with each [Outcome], see [outcome_counts]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OutcomeCounts {
+    pub success: u64,
+    pub client_error: u64,
+    pub server_error: u64,
+    pub cancelled: u64,
+    pub timeout: u64,
+    /// Spans without an outcome, which are excluded from the error rate
+    pub unknown: u64,
+}
+
+impl OutcomeCounts {
+    /// Spans with an outcome
+    pub fn total(&self) -> u64 {
+        self.success + self.client_error + self.server_error + self.cancelled + self.timeout
+    }
+
+    /// Spans whose outcome [Outcome::is_error]
+    pub fn errors(&self) -> u64 {
+        self.server_error + self.timeout
+    }
+
+    /// The fraction of spans with an outcome that were errors, or None when no span has an outcome
+    pub fn error_rate(&self) -> Option<f64> {
+        let total = self.total();
+        (total > 0).then(|| self.errors() as f64 / total as f64)
+    }
+}
+
+/// Count the outcomes of the given spans, see [crate::Span::set_outcome]
+pub fn outcome_counts(spans: &[SpanData]) -> OutcomeCounts {
+    let mut counts = OutcomeCounts::default();
+    for span in spans {
+        let count = match span.outcome {
+            Some(Outcome::Success) => &mut counts.success,
+            Some(Outcome::ClientError) => &mut counts.client_error,
+            Some(Outcome::ServerError) => &mut counts.server_error,
+            Some(Outcome::Cancelled) => &mut counts.cancelled,
+            Some(Outcome::Timeout) => &mut counts.timeout,
+            None => &mut counts.unknown,
+        };
+        *count += 1;
+    }
+    counts
+}
+
+fn duration(span: &SpanData) -> u64 {
+    span.end_instant.saturating_sub(span.start_instant)
+}
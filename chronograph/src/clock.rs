@@ -0,0 +1,118 @@
+//! The monotonic clock backing span instants.
+
+use std::{
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+use crate::schema::ClockSource;
+
+/// Reads nanoseconds elapsed since the chronograph was built from the selected [ClockSource]
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Clock {
+    source: ClockSource,
+    start: Instant,
+    /// The `clock_gettime` clock read instead of [Instant], and its reading when the clock was started
+    #[cfg(unix)]
+    raw: Option<(libc::clockid_t, u64)>,
+}
+
+impl Clock {
+    /// Start a clock from the given source, falling back to [ClockSource::platform_default]
+    /// when it is unsupported on this platform or can't be read at runtime
+    pub(crate) fn new(source: ClockSource) -> Self {
+        let source = match source.is_supported() {
+            true => source,
+            false => ClockSource::platform_default(),
+        };
+        #[cfg(unix)]
+        let raw = match clock_id(source) {
+            Some(id) => match clock_gettime_nanos(id) {
+                Some(raw_start) => Some((id, raw_start)),
+                // the platform default is always read through Instant
+                None => return Self::new(ClockSource::platform_default()),
+            },
+            None => None,
+        };
+        Self {
+            source,
+            start: Instant::now(),
+            #[cfg(unix)]
+            raw,
+        }
+    }
+
+    pub(crate) fn source(&self) -> ClockSource {
+        self.source
+    }
+
+    /// The [Instant] the clock was started at, which is the origin of every span instant
+    pub(crate) fn start(&self) -> Instant {
+        self.start
+    }
+
+    pub(crate) fn elapsed_nanos(&self) -> u64 {
+        #[cfg(unix)]
+        if let Some((id, raw_start)) = self.raw {
+            return clock_gettime_nanos(id)
+                .unwrap_or_default()
+                .saturating_sub(raw_start);
+        }
+        self.start.elapsed().as_nanos() as u64
+    }
+
+    /// The smallest observable tick of the clock, probed the first time it is needed for each source in the process
+    pub(crate) fn resolution(&self) -> Duration {
+        static RESOLUTIONS: [OnceLock<Duration>; 5] = [const { OnceLock::new() }; 5];
+        let index = match self.source {
+            ClockSource::QueryPerformanceCounter => 0,
+            ClockSource::MachAbsoluteTime => 1,
+            ClockSource::ClockMonotonic => 2,
+            ClockSource::ClockMonotonicRaw => 3,
+            ClockSource::Other => 4,
+        };
+        *RESOLUTIONS[index].get_or_init(|| self.probe_resolution())
+    }
+
+    /// Measure the smallest observable tick of the clock
+    fn probe_resolution(&self) -> Duration {
+        // a few samples are enough to find the tick without spinning for long on coarse clocks
+        let mut resolution = u64::MAX;
+        for _ in 0..4 {
+            let first = self.elapsed_nanos();
+            let mut next = self.elapsed_nanos();
+            while next == first {
+                next = self.elapsed_nanos();
+            }
+            resolution = resolution.min(next.saturating_sub(first));
+        }
+        Duration::from_nanos(resolution)
+    }
+}
+
+/// The `clock_gettime` clock backing a source, or None when the source is read through [Instant]
+#[cfg(unix)]
+fn clock_id(source: ClockSource) -> Option<libc::clockid_t> {
+    match source {
+        #[cfg(any(target_os = "linux", target_os = "android", target_vendor = "apple"))]
+        ClockSource::ClockMonotonicRaw => Some(libc::CLOCK_MONOTONIC_RAW),
+        // std reads mach_absolute_time on apple platforms rather than CLOCK_MONOTONIC
+        #[cfg(target_vendor = "apple")]
+        ClockSource::ClockMonotonic => Some(libc::CLOCK_MONOTONIC),
+        _ => None,
+    }
+}
+
+/// Read a clock, or None when it is unavailable, such as on an old kernel or in a restrictive sandbox
+#[cfg(unix)]
+fn clock_gettime_nanos(id: libc::clockid_t) -> Option<u64> {
+    let mut time = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // Safety: `time` is a valid, writable timespec
+    match unsafe { libc::clock_gettime(id, &mut time) } {
+        0 => Some(time.tv_sec as u64 * 1_000_000_000 + time.tv_nsec as u64),
+        _ => None,
+    }
+}
@@ -0,0 +1,245 @@
+//! Merging of span batches collected from multiple processes or hosts onto a single, skew-corrected timeline.
+//!
+//! Each producer's instants are converted to unix time using the calibration anchor every span carries,
+//! its start unix time paired with its start instant. Clock skew between producers is then estimated from traces
+//! that cross producers: a span started in another producer with [crate::Chronograph::start_span_in_trace]
+//! must lie within the root span that originated the trace, which bounds the skew between the two clocks.
+//!
+//! # Example
+//! ```rust
+//! use chronograph::collector::TimelineCollector;
+//! use chronograph::schema::{SpanBatch, SpanData};
+//!
+//! let batch = |span| SpanBatch {
+//!     spans: vec![span],
+//!     metadata: Default::default(),
+//!     dictionary: Vec::new(),
+//! };
+//! // the frontend starts a trace that lasts 10µs
+//! let trace_id = (1_000_000_000u128 << 64) | 7;
+//! let frontend = SpanData {
+//!     span_id: 7,
+//!     trace_id,
+//!     start_unix_time: 1_000_000_000,
+//!     start_instant: 0,
+//!     end_instant: 10_000,
+//!     ..Default::default()
+//! };
+//! // the backend handles it within those 10µs, but its wall clock is 1ms ahead
+//! let backend = SpanData {
+//!     span_id: 3,
+//!     trace_id,
+//!     start_unix_time: 1_001_002_000,
+//!     start_instant: 500,
+//!     end_instant: 6_500,
+//!     ..Default::default()
+//! };
+//! let mut collector = TimelineCollector::default();
+//! collector.add_batch("frontend", batch(frontend));
+//! collector.add_batch("backend", batch(backend));
+//!
+//! let skews = collector.estimate_skew();
+//! assert_eq!(skews[1].producer, "backend");
+//! assert_eq!(skews[1].offset_nanos, -1_000_000);
+//! let timeline = collector.merged_timeline();
+//! assert_eq!(timeline[1].start_unix_nanos(), 1_000_002_000);
+//! ```
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::schema::{SpanBatch, SpanData};
+
+/// Collects batches from multiple producers and merges them onto one timeline
+#[derive(Debug, Clone, Default)]
+pub struct TimelineCollector {
+    producers: Vec<Producer>,
+}
+
+#[derive(Debug, Clone)]
+struct Producer {
+    name: String,
+    spans: Vec<SpanData>,
+}
+
+/// The estimated clock skew of a producer, relative to the first producer added to a [TimelineCollector]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClockSkew {
+    pub producer: String,
+    /// Nanoseconds to add to the producer's unix times to align them with the reference producer
+    pub offset_nanos: i64,
+    /// How many cross-producer spans the estimate was derived from, zero when the producer shares no traces
+    pub constraints: usize,
+}
+
+/// A span on a merged timeline, with its instants convertible to skew-corrected unix times
+#[derive(Debug, Clone, Copy)]
+pub struct TimelineSpan<'a> {
+    pub producer: &'a str,
+    pub span: &'a SpanData,
+    /// Nanoseconds to add to an instant of the span to get its corrected unix time
+    instant_offset: i64,
+}
+
+impl TimelineSpan<'_> {
+    /// Convert an instant recorded by this span's producer to a skew-corrected unix time, in nanoseconds
+    pub fn to_unix_nanos(&self, instant: u64) -> i64 {
+        instant as i64 + self.instant_offset
+    }
+
+    pub fn start_unix_nanos(&self) -> i64 {
+        self.to_unix_nanos(self.span.start_instant)
+    }
+
+    pub fn end_unix_nanos(&self) -> i64 {
+        self.to_unix_nanos(self.span.end_instant)
+    }
+}
+
+impl TimelineCollector {
+    /// Add a batch exported by the named producer. The first producer added is the reference clock.
+    pub fn add_batch(&mut self, producer: &str, batch: SpanBatch) {
+        match self.producers.iter_mut().find(|x| x.name == producer) {
+            Some(existing) => existing.spans.extend(batch.spans),
+            None => self.producers.push(Producer {
+                name: producer.to_owned(),
+                spans: batch.spans,
+            }),
+        }
+    }
+
+    /// Estimate the clock skew of every producer, in the order they were added
+    pub fn estimate_skew(&self) -> Vec<ClockSkew> {
+        let instant_offsets: Vec<i64> = self.producers.iter().map(instant_offset).collect();
+        let wall = |producer: usize, instant: u64| instant as i64 + instant_offsets[producer];
+
+        // root spans of each trace, which are the only spans that can be started in another producer
+        let mut roots: HashMap<u128, Vec<(usize, &SpanData)>> = HashMap::new();
+        for (producer, x) in self.producers.iter().enumerate() {
+            for span in x.spans.iter().filter(|x| x.parent_span_id.is_none()) {
+                roots
+                    .entry(span.trace_id)
+                    .or_default()
+                    .push((producer, span));
+            }
+        }
+
+        // bounds of `skew[callee] - skew[origin]` from each callee span contained in the span that originated its trace
+        let mut bounds: HashMap<(usize, usize), Vec<(i64, i64)>> = HashMap::new();
+        for (trace_id, spans) in roots.iter() {
+            let Some(&(origin, origin_span)) =
+                spans.iter().find(|(_, x)| is_trace_origin(*trace_id, x))
+            else {
+                continue;
+            };
+            for &(callee, span) in spans.iter().filter(|(x, _)| *x != origin) {
+                let lower =
+                    wall(origin, origin_span.start_instant) - wall(callee, span.start_instant);
+                let upper = wall(origin, origin_span.end_instant) - wall(callee, span.end_instant);
+                // a callee outliving the origin was not awaited, so it doesn't bound the skew
+                if lower <= upper {
+                    bounds
+                        .entry((callee, origin))
+                        .or_default()
+                        .push((lower, upper));
+                }
+            }
+        }
+
+        let mut edges: Vec<Vec<(usize, i64)>> = vec![Vec::new(); self.producers.len()];
+        let mut constraints = vec![0; self.producers.len()];
+        for (&(callee, origin), bounds) in bounds.iter() {
+            let offset = estimate_offset(bounds);
+            edges[origin].push((callee, offset));
+            edges[callee].push((origin, -offset));
+            constraints[origin] += bounds.len();
+            constraints[callee] += bounds.len();
+        }
+
+        // propagate offsets outward from the reference producer
+        let mut offsets: Vec<Option<i64>> = vec![None; self.producers.len()];
+        let mut queue = VecDeque::new();
+        if !offsets.is_empty() {
+            offsets[0] = Some(0);
+            queue.push_back(0);
+        }
+        while let Some(producer) = queue.pop_front() {
+            let Some(offset) = offsets[producer] else {
+                continue;
+            };
+            for &(next, relative) in edges[producer].iter() {
+                if offsets[next].is_none() {
+                    offsets[next] = Some(offset + relative);
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        self.producers
+            .iter()
+            .zip(offsets)
+            .zip(constraints)
+            .map(|((producer, offset), constraints)| ClockSkew {
+                producer: producer.name.clone(),
+                offset_nanos: offset.unwrap_or_default(),
+                constraints,
+            })
+            .collect()
+    }
+
+    /// All spans from every producer, ordered by their skew-corrected start time
+    pub fn merged_timeline(&self) -> Vec<TimelineSpan<'_>> {
+        let skews = self.estimate_skew();
+        let mut timeline: Vec<TimelineSpan> = self
+            .producers
+            .iter()
+            .zip(skews)
+            .flat_map(|(producer, skew)| {
+                let instant_offset = instant_offset(producer) + skew.offset_nanos;
+                producer.spans.iter().map(move |span| TimelineSpan {
+                    producer: &producer.name,
+                    span,
+                    instant_offset,
+                })
+            })
+            .collect();
+        timeline.sort_by_key(|x| x.start_unix_nanos());
+        timeline
+    }
+}
+
+/// The offset from a producer's instants to its unix time, as the median over every span's calibration anchor.
+///
+/// Reading the unix time and instant of a span start isn't atomic, so the median discards outliers from preemption.
+fn instant_offset(producer: &Producer) -> i64 {
+    let mut offsets: Vec<i64> = producer
+        .spans
+        .iter()
+        .map(|x| x.start_unix_time - x.start_instant as i64)
+        .collect();
+    if offsets.is_empty() {
+        return 0;
+    }
+    offsets.sort_unstable();
+    offsets[offsets.len() / 2]
+}
+
+/// Whether the span started its trace, since new trace ids combine the start unix time and span id
+fn is_trace_origin(trace_id: u128, span: &SpanData) -> bool {
+    trace_id as u64 == span.span_id && (trace_id >> 64) as u64 == span.start_unix_time as u64
+}
+
+/// The midpoint of the intersection of all bounds, or the median midpoint when they are inconsistent
+fn estimate_offset(bounds: &[(i64, i64)]) -> i64 {
+    let lower = bounds.iter().map(|x| x.0).max().unwrap_or_default();
+    let upper = bounds.iter().map(|x| x.1).min().unwrap_or_default();
+    if lower <= upper {
+        return midpoint(lower, upper);
+    }
+    let mut midpoints: Vec<i64> = bounds.iter().map(|x| midpoint(x.0, x.1)).collect();
+    midpoints.sort_unstable();
+    midpoints[midpoints.len() / 2]
+}
+
+fn midpoint(lower: i64, upper: i64) -> i64 {
+    lower + (upper - lower) / 2
+}
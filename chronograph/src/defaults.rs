@@ -0,0 +1,59 @@
+//! Datapoints recorded on every sampled span, see [crate::ChronographBuilder::with_default_datapoint].
+
+use crate::schema::{DatapointId, RecordValue};
+
+/// Evaluates the value of a default datapoint
+pub(crate) type DatapointFn = Box<dyn Fn() -> RecordValue + Send + Sync>;
+
+/// The default datapoints of a chronograph, in the order they were added
+#[derive(Default)]
+pub(crate) struct DefaultDatapoints {
+    datapoints: Vec<(DatapointId, DefaultValue)>,
+}
+
+enum DefaultValue {
+    Static(RecordValue),
+    OnStart(DatapointFn),
+    OnEnd(DatapointFn),
+}
+
+impl DefaultDatapoints {
+    pub(crate) fn add_static(&mut self, datapoint_id: DatapointId, value: RecordValue) {
+        self.datapoints
+            .push((datapoint_id, DefaultValue::Static(value)));
+    }
+
+    pub(crate) fn add_on_start(&mut self, datapoint_id: DatapointId, f: DatapointFn) {
+        self.datapoints
+            .push((datapoint_id, DefaultValue::OnStart(f)));
+    }
+
+    pub(crate) fn add_on_end(&mut self, datapoint_id: DatapointId, f: DatapointFn) {
+        self.datapoints.push((datapoint_id, DefaultValue::OnEnd(f)));
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.datapoints.len()
+    }
+
+    /// Static values, and values evaluated now that a span is starting
+    pub(crate) fn on_start(&self) -> impl Iterator<Item = (DatapointId, RecordValue)> + '_ {
+        self.datapoints
+            .iter()
+            .filter_map(|(id, value)| match value {
+                DefaultValue::Static(value) => Some((*id, value.clone())),
+                DefaultValue::OnStart(f) => Some((*id, f())),
+                DefaultValue::OnEnd(_) => None,
+            })
+    }
+
+    /// Values evaluated now that a span is ending
+    pub(crate) fn on_end(&self) -> impl Iterator<Item = (DatapointId, RecordValue)> + '_ {
+        self.datapoints
+            .iter()
+            .filter_map(|(id, value)| match value {
+                DefaultValue::OnEnd(f) => Some((*id, f())),
+                _ => None,
+            })
+    }
+}
@@ -0,0 +1,34 @@
+//! Errors caused by misuse of a [crate::Span], reported to [crate::ChronographBuilder::with_error_hook].
+
+use std::fmt::Display;
+
+use crate::schema::DatapointId;
+
+/// Notified of [SpanError]s, see [crate::ChronographBuilder::with_error_hook]
+pub type ErrorHook = Box<dyn Fn(&SpanError) + Send + Sync>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpanError {
+    /// A datapoint was recorded to a span after [crate::Span::end], so the record was discarded
+    RecordAfterEnd {
+        span_id: u64,
+        datapoint_id: DatapointId,
+    },
+}
+
+impl Display for SpanError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::RecordAfterEnd {
+                span_id,
+                datapoint_id,
+            } => write!(
+                f,
+                "datapoint {} recorded after span {span_id} ended",
+                datapoint_id.value
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SpanError {}
@@ -0,0 +1,257 @@
+//! Human-readable formatting of spans, shared by the [std::fmt::Display] of [SpanData] and [crate::schema::SpanBatch]
+//! and by the [crate::recorder::debug::DebugPrintRecorder].
+
+use std::fmt::Write as _;
+
+use crate::{
+    registry,
+    schema::{
+        DatapointId, DictionaryEntry, RecordValue, SourceLocation, SpanData, SpanKind, SpanStatus,
+        ThreadInfo,
+    },
+};
+
+pub(crate) const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+pub(crate) const DIM: &str = "\x1b[2m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const CYAN: &str = "\x1b[36m";
+
+/// Formats spans as printed by a [crate::recorder::debug::DebugPrintRecorder], also used by the [std::fmt::Display] of spans and batches
+pub(crate) struct SpanFormatter<'a> {
+    pub(crate) color: bool,
+    /// Names to fall back to for datapoints missing from the [registry], such as a batch's dictionary
+    pub(crate) dictionary: &'a [DictionaryEntry],
+}
+
+impl SpanFormatter<'_> {
+    /// Format a span, ending every line with a newline
+    pub(crate) fn format_span(&self, span: &SpanData) -> String {
+        let mut text = String::new();
+        let duration = span.end_instant.saturating_sub(span.start_instant);
+        write!(
+            text,
+            "{}{}{} span_id={}",
+            self.style(BOLD),
+            span.name
+                .map(|x| self.name(x))
+                .unwrap_or_else(|| "span".to_owned()),
+            self.style(RESET),
+            span.span_id
+        )
+        .ok();
+        if span.kind != SpanKind::Internal {
+            write!(text, " kind={:?}", span.kind).ok();
+        }
+        if let Some(parent_span_id) = span.parent_span_id {
+            write!(text, " parent_span_id={parent_span_id}").ok();
+        }
+        if let Some(outcome) = span.outcome {
+            write!(text, " outcome={outcome:?}").ok();
+        }
+        if let Some(partition_key) = span.partition_key {
+            write!(text, " partition_key={partition_key}").ok();
+        }
+        write!(
+            text,
+            " trace_id={:032x} duration={}{}{} ",
+            span.trace_id,
+            self.style(CYAN),
+            format_nanos(duration),
+            self.style(RESET)
+        )
+        .ok();
+        match &span.status {
+            SpanStatus::Ok => write!(text, "{}ok{}", self.style(GREEN), self.style(RESET)),
+            SpanStatus::Error(message) => write!(
+                text,
+                "{}error{}{}",
+                self.style(RED),
+                message
+                    .as_deref()
+                    .map(|x| format!(": {x}"))
+                    .unwrap_or_default(),
+                self.style(RESET)
+            ),
+        }
+        .ok();
+        if let Some(location) = span.location {
+            write!(
+                text,
+                " {}at {}{}",
+                self.style(DIM),
+                self.format_location(location),
+                self.style(RESET)
+            )
+            .ok();
+        }
+        if let Some(thread) = span.thread {
+            write!(
+                text,
+                " {}on thread {}{}",
+                self.style(DIM),
+                self.format_thread(thread),
+                self.style(RESET)
+            )
+            .ok();
+        }
+        text.push('\n');
+        for link in span.links.iter() {
+            writeln!(
+                text,
+                "  {}linked to span_id={} trace_id={:032x}{}",
+                self.style(DIM),
+                link.span_id,
+                link.trace_id,
+                self.style(RESET)
+            )
+            .ok();
+        }
+        for attribute in span.attributes.iter() {
+            writeln!(
+                text,
+                "  {}{} = {}{}",
+                self.style(DIM),
+                self.name(attribute.key),
+                format_value(&attribute.value, span.start_instant),
+                self.style(RESET)
+            )
+            .ok();
+        }
+        let mut previous = span.start_instant;
+        for record in span.records.iter() {
+            match record.value {
+                RecordValue::Instant(instant) => {
+                    let offset = format!(
+                        "+{}",
+                        format_nanos(instant.saturating_sub(span.start_instant))
+                    );
+                    let delta = format!("(+{})", format_nanos(instant.saturating_sub(previous)));
+                    write!(
+                        text,
+                        "  {}{offset:<12} {delta:<12}{} {}",
+                        self.style(CYAN),
+                        self.style(RESET),
+                        self.name(record.datapoint_id)
+                    )
+                    .ok();
+                    self.write_location(&mut text, record.location);
+                    previous = instant;
+                }
+                _ => {
+                    write!(
+                        text,
+                        "  {} = {}",
+                        self.name(record.datapoint_id),
+                        format_value(&record.value, span.start_instant)
+                    )
+                    .ok();
+                    if let Some(unit) = record.unit {
+                        write!(text, " {}{unit:?}{}", self.style(DIM), self.style(RESET)).ok();
+                    }
+                    if let Some(instant) = record.instant {
+                        write!(
+                            text,
+                            " {}at +{}{}",
+                            self.style(DIM),
+                            format_nanos(instant.saturating_sub(span.start_instant)),
+                            self.style(RESET)
+                        )
+                        .ok();
+                    }
+                    self.write_location(&mut text, record.location);
+                }
+            }
+        }
+        if let Some(backtrace) = &span.backtrace {
+            for line in backtrace.lines() {
+                writeln!(text, "  {}| {line}{}", self.style(DIM), self.style(RESET)).ok();
+            }
+        }
+        text
+    }
+
+    /// End a record's line, with where it was recorded when known
+    fn write_location(&self, text: &mut String, location: Option<SourceLocation>) {
+        if let Some(location) = location {
+            write!(
+                text,
+                " {}at {}{}",
+                self.style(DIM),
+                self.format_location(location),
+                self.style(RESET)
+            )
+            .ok();
+        }
+        text.push('\n');
+    }
+
+    fn style(&self, code: &'static str) -> &'static str {
+        match self.color {
+            true => code,
+            false => "",
+        }
+    }
+
+    /// The registered name of a datapoint, or its raw id
+    fn name(&self, id: DatapointId) -> String {
+        registry::resolve_name(id)
+            .or_else(|| {
+                self.dictionary
+                    .iter()
+                    .find(|x| x.id == id)
+                    .map(|x| x.name.clone())
+            })
+            .unwrap_or_else(|| format!("#{}", id.value))
+    }
+
+    fn format_thread(&self, thread: ThreadInfo) -> String {
+        match thread.name {
+            Some(thread_name) => format!("{} ({})", thread.id, self.name(thread_name)),
+            None => thread.id.to_string(),
+        }
+    }
+
+    fn format_location(&self, location: SourceLocation) -> String {
+        format!("{}:{}", self.name(location.file), location.line)
+    }
+}
+
+pub(crate) fn format_nanos(nanos: u64) -> String {
+    match nanos {
+        0..1_000 => format!("{nanos}ns"),
+        1_000..1_000_000 => format!("{:.1}µs", nanos as f64 / 1e3),
+        1_000_000..1_000_000_000 => format!("{:.3}ms", nanos as f64 / 1e6),
+        _ => format!("{:.3}s", nanos as f64 / 1e9),
+    }
+}
+
+fn format_value(value: &RecordValue, start_instant: u64) -> String {
+    match value {
+        RecordValue::Instant(x) => format!("+{}", format_nanos(x.saturating_sub(start_instant))),
+        RecordValue::UnixTime(x) => format!("{x}ns since epoch"),
+        RecordValue::Duration(x) => format_nanos(*x),
+        RecordValue::Utf8String(x) => format!("{x:?}"),
+        RecordValue::I32(x) => x.to_string(),
+        RecordValue::I64(x) => x.to_string(),
+        RecordValue::I128(x) => x.to_string(),
+        RecordValue::U32(x) => x.to_string(),
+        RecordValue::U64(x) => x.to_string(),
+        RecordValue::U128(x) => x.to_string(),
+        RecordValue::F32(x) => x.to_string(),
+        RecordValue::F64(x) => x.to_string(),
+        RecordValue::Bool(x) => x.to_string(),
+        RecordValue::Bytes(x) => x.iter().fold(String::from("0x"), |mut hex, byte| {
+            write!(hex, "{byte:02x}").ok();
+            hex
+        }),
+        RecordValue::List(x) => format!(
+            "[{}]",
+            x.iter()
+                .map(|x| format_value(x, start_instant))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
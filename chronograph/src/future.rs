@@ -0,0 +1,99 @@
+//! Instrumentation of futures, so async code can use the thread-local span across await points.
+//!
+//! A future wrapped with [SpanFutureExt::in_span] sets its span as the thread-local span while it is polled,
+//! whichever thread polls it, and records the span when it completes.
+//!
+//! # Example
+//! ```rust
+//! use std::{future::Future, pin::pin, task::{Context, Poll, Waker}};
+//! use chronograph::future::SpanFutureExt;
+//! use chronograph::{with_threadlocal_span, Chronograph};
+//!
+//! let chronograph = Chronograph::builder().build();
+//! let future = async {
+//!     with_threadlocal_span(|span| {
+//!         span.record_instant("polled");
+//!     });
+//!     42
+//! };
+//! let mut future = pin!(future.in_span(chronograph.start_span()));
+//! let mut cx = Context::from_waker(Waker::noop());
+//! assert_eq!(future.as_mut().poll(&mut cx), Poll::Ready(42));
+//! ```
+
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::{
+    local::{end_threadlocal_spans, enter_threadlocal_spans, exit_threadlocal_spans},
+    Span,
+};
+
+/// A future that runs within a [Span], see [SpanFutureExt::in_span]
+#[derive(Debug)]
+pub struct Instrumented<F> {
+    future: F,
+    /// The span of the future followed by any children left open within it across an await point
+    spans: Vec<Span>,
+}
+
+/// Extends every future with [SpanFutureExt::in_span]
+pub trait SpanFutureExt: Future + Sized {
+    /// Push `span` as the thread-local span whenever the future is polled, and record it when the future completes
+    fn in_span(self, span: Span) -> Instrumented<Self> {
+        Instrumented {
+            future: self,
+            spans: vec![span],
+        }
+    }
+}
+
+impl<F: Future> SpanFutureExt for F {}
+
+impl<F> Instrumented<F> {
+    /// The span of the future, or None once the future has completed
+    pub fn span_mut(&mut self) -> Option<&mut Span> {
+        self.spans.first_mut()
+    }
+}
+
+impl<F: Future> Future for Instrumented<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Safety: `future` is pinned along with the wrapper and never moved, while `span` is never treated as pinned
+        let this = unsafe { self.get_unchecked_mut() };
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+        let poll = {
+            let _guard = ThreadLocalGuard::enter(&mut this.spans);
+            future.poll(cx)
+        };
+        if poll.is_ready() {
+            // ending the spans records them
+            end_threadlocal_spans(std::mem::take(&mut this.spans));
+        }
+        poll
+    }
+}
+
+/// Pushes spans onto the thread-local span stack, removing them again even if the poll panics
+struct ThreadLocalGuard<'a> {
+    spans: &'a mut Vec<Span>,
+    depth: usize,
+}
+
+impl<'a> ThreadLocalGuard<'a> {
+    fn enter(spans: &'a mut Vec<Span>) -> Self {
+        let depth = enter_threadlocal_spans(std::mem::take(spans));
+        Self { spans, depth }
+    }
+}
+
+impl Drop for ThreadLocalGuard<'_> {
+    fn drop(&mut self) {
+        *self.spans = exit_threadlocal_spans(self.depth);
+    }
+}
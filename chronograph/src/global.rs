@@ -13,15 +13,33 @@
 //! span.record_instant("my_op_end");
 //! ```
 
-use crate::{recorder::SpanRecorder, Chronograph};
-use std::sync::OnceLock;
+use crate::{recorder::SpanRecorder, Chronograph, Span};
+use std::{
+    cell::RefCell,
+    sync::{Arc, OnceLock},
+};
 
 static GLOBAL_CHRONOGRAPH: OnceLock<Chronograph> = OnceLock::new();
 static NOOP_CHRONOGRAPH: OnceLock<Chronograph> = OnceLock::new();
 
-/// Get a reference to the global chronograph.
+thread_local! {
+    /// Overrides the global chronograph for the current thread while set, sharing the context of the overriding chronograph
+    static THREAD_GLOBAL: RefCell<Option<Chronograph>> = const { RefCell::new(None) };
+}
+
+/// Get the global chronograph, or the chronograph overriding it on the current thread, see [with_scoped_global].
 /// If `init` has not been called, a no-op chronograph will be returned.
-pub fn global() -> &'static Chronograph {
+///
+/// The returned chronograph shares the context of the one passed to [init] or [with_scoped_global],
+/// so it is a cheap handle rather than a new chronograph. [with_global] avoids even that when only borrowing it.
+pub fn global() -> Chronograph {
+    scoped_global().unwrap_or_else(|| Chronograph {
+        context: Arc::clone(&static_global().context),
+    })
+}
+
+/// The chronograph passed to [init], or a no-op chronograph if `init` has not been called
+fn static_global() -> &'static Chronograph {
     fn get_noop_chronograph() -> &'static Chronograph {
         NOOP_CHRONOGRAPH.get_or_init(|| {
             Chronograph::builder()
@@ -34,6 +52,43 @@ pub fn global() -> &'static Chronograph {
         .unwrap_or_else(get_noop_chronograph)
 }
 
+/// Run `f` with the chronograph [global] returns, borrowing it rather than getting a handle to it.
+///
+/// Thread-local spans and the macros start their spans from this chronograph.
+///
+/// # Example
+/// ```rust
+/// use chronograph::with_global;
+/// let mut span = with_global(|chronograph| chronograph.start_span());
+/// span.record_value("count", 42);
+/// ```
+pub fn with_global<R>(f: impl FnOnce(&Chronograph) -> R) -> R {
+    match scoped_global() {
+        Some(chronograph) => f(&chronograph),
+        None => f(static_global()),
+    }
+}
+
+/// Start a span from the chronograph [with_global] runs with, keeping the caller's location
+#[track_caller]
+pub(crate) fn start_global_span() -> Span {
+    match scoped_global() {
+        Some(chronograph) => chronograph.start_span(),
+        None => static_global().start_span(),
+    }
+}
+
+/// The chronograph overriding the global chronograph on the current thread, if any.
+///
+/// It is shared rather than borrowed, so it can be used while the override itself is replaced.
+fn scoped_global() -> Option<Chronograph> {
+    THREAD_GLOBAL.with(|x| {
+        x.borrow().as_ref().map(|x| Chronograph {
+            context: Arc::clone(&x.context),
+        })
+    })
+}
+
 /// Initialize the global chronograph.
 ///
 /// # Panics
@@ -44,3 +99,35 @@ pub fn init(chronograph: Chronograph) {
         panic!("chronograph::init has already been called");
     }
 }
+
+/// Override what [global] returns on the current thread while `f` runs, restoring the previous value afterwards.
+///
+/// Calls can be nested, with the innermost chronograph taking precedence. Other threads, and the current thread
+/// outside of `f`, continue to see the chronograph passed to [init].
+///
+/// Thread-local spans, the macros and [with_global] start their spans from the overriding chronograph.
+///
+/// # Example
+/// ```rust
+/// use chronograph::{global, with_scoped_global, Chronograph};
+/// let chronograph = Chronograph::builder().with_sample_rate(1).build();
+/// with_scoped_global(&chronograph, || {
+///     let mut span = global().start_span();
+///     span.record_value("count", 42);
+/// });
+/// ```
+pub fn with_scoped_global<R>(chronograph: &Chronograph, f: impl FnOnce() -> R) -> R {
+    /// Restores the previous override, even if `f` panics
+    struct Restore(Option<Chronograph>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let previous = self.0.take();
+            THREAD_GLOBAL.with(|x| *x.borrow_mut() = previous);
+        }
+    }
+    let scoped = Chronograph {
+        context: Arc::clone(&chronograph.context),
+    };
+    let _restore = Restore(THREAD_GLOBAL.with(|x| x.borrow_mut().replace(scoped)));
+    f()
+}
@@ -0,0 +1,175 @@
+//! Live introspection of a running [Chronograph] over a simple HTTP/JSON endpoint.
+//!
+//! Enabled by the `introspect` feature. Serves the following paths:
+//! - `/`: everything below, in a single JSON object
+//! - `/stats`: the [crate::recorder::batch::RecorderStats] of the recorder, or `null` if it is not a batching recorder
+//! - `/sampler`: the sampling configuration
+//! - `/spans`: the most recently recorded spans, when enabled by [crate::ChronographBuilder::with_introspection]
+//! - `/watchdog`: the id, name and age of every span watched by [crate::ChronographBuilder::with_watchdog],
+//!   or `null` if there is no watchdog
+//!
+//! # Example
+//! ```rust,no_run
+//! use chronograph::introspect::IntrospectionServer;
+//! use chronograph::Chronograph;
+//!
+//! let chronograph = Chronograph::builder().with_introspection(128).build();
+//! let server = IntrospectionServer::bind(&chronograph, "127.0.0.1:9898").unwrap();
+//! println!("serving introspection on http://{}", server.local_addr());
+//! ```
+
+use std::{
+    collections::VecDeque,
+    io,
+    net::{SocketAddr, ToSocketAddrs},
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    json::{spans_json, string_json, JsonResponse, JsonServer},
+    recorder::batch::RecorderStats,
+    registry,
+    schema::SpanData,
+    Chronograph, ChronographContext,
+};
+
+/// A ring of the most recently recorded spans
+#[derive(Debug)]
+pub(crate) struct RecentSpans {
+    capacity: usize,
+    spans: Mutex<VecDeque<SpanData>>,
+}
+
+impl RecentSpans {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            spans: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    pub(crate) fn push(&self, span: &SpanData) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut spans = self.spans.lock().unwrap_or_else(|e| e.into_inner());
+        if spans.len() == self.capacity {
+            spans.pop_front();
+        }
+        spans.push_back(span.clone());
+    }
+
+    fn snapshot(&self) -> Vec<SpanData> {
+        let spans = self.spans.lock().unwrap_or_else(|e| e.into_inner());
+        spans.iter().cloned().collect()
+    }
+}
+
+/// Serves introspection requests from a dedicated thread until it is dropped or the [Chronograph] is dropped
+#[derive(Debug)]
+pub struct IntrospectionServer {
+    server: JsonServer,
+}
+
+impl IntrospectionServer {
+    /// Bind to the given address and start serving requests for the given [Chronograph]
+    pub fn bind(chronograph: &Chronograph, addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let context = Arc::downgrade(&chronograph.context);
+        let server = JsonServer::bind(addr, "chronograph introspection", move |path, _| {
+            let context = context.upgrade()?;
+            Some(route(&context, path))
+        })?;
+        Ok(Self { server })
+    }
+
+    /// The address the server is listening on
+    pub fn local_addr(&self) -> SocketAddr {
+        self.server.local_addr()
+    }
+}
+
+fn route(context: &ChronographContext, path: &str) -> JsonResponse {
+    match path {
+        "/" => Ok(format!(
+            "{{\"stats\":{},\"sampler\":{},\"recent_spans\":{},\"watchdog\":{}}}",
+            stats_json(context),
+            sampler_json(context),
+            recent_spans_json(context),
+            watchdog_json(context)
+        )),
+        "/stats" => Ok(stats_json(context)),
+        "/sampler" => Ok(sampler_json(context)),
+        "/spans" => Ok(recent_spans_json(context)),
+        "/watchdog" => Ok(watchdog_json(context)),
+        _ => Err((404, format!("unknown path: {path}"))),
+    }
+}
+
+fn stats_json(context: &ChronographContext) -> String {
+    match context.recorder.stats() {
+        Some(RecorderStats {
+            batch_size_threshold,
+            queue_len,
+            spans_dropped,
+            batches_exported,
+            exporter_errors,
+            last_export_latency,
+        }) => format!(
+            "{{\"batch_size_threshold\":{batch_size_threshold},\"queue_len\":{queue_len},\"spans_dropped\":{spans_dropped},\"batches_exported\":{batches_exported},\"exporter_errors\":{exporter_errors},\"last_export_latency_ns\":{}}}",
+            last_export_latency.as_nanos()
+        ),
+        None => "null".to_owned(),
+    }
+}
+
+fn sampler_json(context: &ChronographContext) -> String {
+    format!(
+        "{{\"sampler\":\"{}\",\"sample_rate\":{},\"name_rate_limit\":{}}}",
+        context.sample_rate.name(),
+        context.sample_rate.rate(),
+        context
+            .name_rate_limiter
+            .as_ref()
+            .map(|x| format!(
+                "{{\"spans_per_second\":{},\"burst\":{}}}",
+                x.spans_per_second, x.burst
+            ))
+            .unwrap_or_else(|| "null".to_owned())
+    )
+}
+
+fn recent_spans_json(context: &ChronographContext) -> String {
+    let spans = context
+        .recent_spans
+        .as_ref()
+        .map(|x| x.snapshot())
+        .unwrap_or_default();
+    spans_json(&spans)
+}
+
+fn watchdog_json(context: &ChronographContext) -> String {
+    let Some(watchdog) = &context.watchdog else {
+        return "null".to_owned();
+    };
+    let now = context.clock.elapsed_nanos();
+    let spans = watchdog
+        .watched()
+        .into_iter()
+        .map(|(span_id, name, start_instant)| {
+            format!(
+                "{{\"span_id\":{span_id},\"name\":{},\"age_ns\":{}}}",
+                name.map(|x| match registry::resolve_name(x) {
+                    Some(name) => string_json(&name),
+                    None => x.value.to_string(),
+                })
+                .unwrap_or_else(|| "null".to_owned()),
+                now.saturating_sub(start_instant)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    format!(
+        "{{\"timeout_ns\":{},\"spans\":[{spans}]}}",
+        watchdog.timeout().as_nanos()
+    )
+}
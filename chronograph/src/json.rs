@@ -0,0 +1,359 @@
+//! Rendering of span data as JSON, and a minimal HTTP server for it, such as the introspection endpoint of the `introspect` feature.
+//!
+//! # Example
+//! ```rust
+//! use chronograph::{json::span_json, Chronograph};
+//!
+//! let chronograph = Chronograph::builder().build();
+//! let mut span = chronograph.start_span();
+//! span.record_value("count", 42u64);
+//! let json = span_json(&span.finish().unwrap());
+//! assert!(json.contains("\"type\":\"U64\",\"value\":42"));
+//! ```
+
+use std::{
+    fmt::Write as _,
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use crate::{
+    registry,
+    schema::{RecordValue, SourceLocation, SpanData, SpanStatus, ThreadInfo, Unit},
+};
+
+/// The body of a successful response, or the status code and plain text message of a failed one
+pub type JsonResponse = Result<String, (u16, String)>;
+
+/// Serves JSON over HTTP from a dedicated thread until it is dropped
+///
+/// Each request is answered by a handler given the path and the query string of the request target.
+/// The handler returns `None` to stop the server, such as once what it serves is gone.
+#[derive(Debug)]
+pub struct JsonServer {
+    local_addr: SocketAddr,
+    stopped: Arc<AtomicBool>,
+}
+
+impl JsonServer {
+    /// Bind to the given address and start serving requests with `handler` from a thread with the given name
+    pub fn bind<F>(addr: impl ToSocketAddrs, thread_name: &str, handler: F) -> io::Result<Self>
+    where
+        F: FnMut(&str, &str) -> Option<JsonResponse> + Send + 'static,
+    {
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        let stopped = Arc::new(AtomicBool::new(false));
+        let thread_stopped = Arc::clone(&stopped);
+        std::thread::Builder::new()
+            .name(thread_name.to_owned())
+            .spawn(move || serve(listener, handler, thread_stopped))?;
+        Ok(Self {
+            local_addr,
+            stopped,
+        })
+    }
+
+    /// The address the server is listening on
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+impl Drop for JsonServer {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+        // wake the blocking accept so the thread can observe the stop flag
+        TcpStream::connect_timeout(&self.local_addr, Duration::from_secs(1)).ok();
+    }
+}
+
+fn serve(
+    listener: TcpListener,
+    mut handler: impl FnMut(&str, &str) -> Option<JsonResponse>,
+    stopped: Arc<AtomicBool>,
+) {
+    for stream in listener.incoming() {
+        if stopped.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Ok(stream) = stream {
+            if let Ok(false) = handle(stream, &mut handler) {
+                return;
+            }
+        }
+    }
+}
+
+/// Answer a single request, returning false when the handler stopped the server
+fn handle(
+    mut stream: TcpStream,
+    handler: &mut impl FnMut(&str, &str) -> Option<JsonResponse>,
+) -> io::Result<bool> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    // read until the end of the request headers, which may arrive over several reads
+    let mut request = [0u8; 4096];
+    let mut len = 0;
+    while len < request.len() && !request[..len].windows(4).any(|x| x == b"\r\n\r\n") {
+        match stream.read(&mut request[len..])? {
+            0 => break,
+            n => len += n,
+        }
+    }
+    let request = String::from_utf8_lossy(&request[..len]);
+    let target = request
+        .lines()
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .unwrap_or("/");
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let Some(response) = handler(path, query) else {
+        return Ok(false);
+    };
+    match response {
+        Ok(body) => write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        ),
+        Err((status, message)) => write!(
+            stream,
+            "HTTP/1.1 {} {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            match status {
+                400 => "Bad Request",
+                404 => "Not Found",
+                _ => "Internal Server Error",
+            },
+            message.len(),
+            message
+        ),
+    }?;
+    Ok(true)
+}
+
+/// Format a span as a JSON object, resolving names through the [registry]
+pub fn span_json(span: &SpanData) -> String {
+    let mut json = String::new();
+    write_span_json(&mut json, span);
+    json
+}
+
+/// Format spans as a JSON array of the objects of [span_json]
+pub fn spans_json<'a>(spans: impl IntoIterator<Item = &'a SpanData>) -> String {
+    let mut json = String::from("[");
+    for (i, span) in spans.into_iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        write_span_json(&mut json, span);
+    }
+    json.push(']');
+    json
+}
+
+fn write_span_json(json: &mut String, span: &SpanData) {
+    write!(
+        json,
+        "{{\"span_id\":{},\"parent_span_id\":{},\"trace_id\":\"{:032x}\",\"name\":{},\"kind\":\"{:?}\",\"start_unix_time\":{},\"start_instant\":{},\"end_instant\":{},\"status\":{},\"outcome\":{},\"records\":[",
+        span.span_id,
+        option_json(span.parent_span_id),
+        span.trace_id,
+        span.name
+            .map(|x| match registry::resolve_name(x) {
+                Some(name) => string_json(&name),
+                None => x.value.to_string(),
+            })
+            .unwrap_or_else(|| "null".to_owned()),
+        span.kind,
+        span.start_unix_time,
+        span.start_instant,
+        span.end_instant,
+        match &span.status {
+            SpanStatus::Ok => "\"ok\"".to_owned(),
+            SpanStatus::Error(message) => format!(
+                "{{\"error\":{}}}",
+                message
+                    .as_deref()
+                    .map(string_json)
+                    .unwrap_or_else(|| "null".to_owned())
+            ),
+        },
+        span.outcome
+            .map(|x| format!("\"{x:?}\""))
+            .unwrap_or_else(|| "null".to_owned())
+    )
+    .ok();
+    for (i, record) in span.records.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        write!(json, "{{\"datapoint_id\":{},", record.datapoint_id.value).ok();
+        write_value_json(json, &record.value);
+        match record.unit {
+            Some(Unit::Custom(code)) => write!(json, ",\"unit\":{code}"),
+            Some(unit) => write!(json, ",\"unit\":\"{unit:?}\""),
+            None => Ok(()),
+        }
+        .ok();
+        if let Some(location) = record.location {
+            write!(json, ",\"location\":{}", location_json(location)).ok();
+        }
+        if let Some(instant) = record.instant {
+            write!(json, ",\"instant\":{instant}").ok();
+        }
+        json.push('}');
+    }
+    json.push_str("],\"attributes\":[");
+    for (i, attribute) in span.attributes.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        write!(json, "{{\"key\":{},", attribute.key.value).ok();
+        write_value_json(json, &attribute.value);
+        json.push('}');
+    }
+    json.push_str("],\"links\":[");
+    for (i, link) in span.links.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        write!(
+            json,
+            "{{\"span_id\":{},\"trace_id\":\"{:032x}\"}}",
+            link.span_id, link.trace_id
+        )
+        .ok();
+    }
+    write!(
+        json,
+        "],\"location\":{},\"thread\":{},\"backtrace\":{},\"partition_key\":{}}}",
+        span.location
+            .map(location_json)
+            .unwrap_or_else(|| "null".to_owned()),
+        span.thread
+            .map(thread_json)
+            .unwrap_or_else(|| "null".to_owned()),
+        span.backtrace
+            .as_deref()
+            .map(string_json)
+            .unwrap_or_else(|| "null".to_owned()),
+        span.partition_key
+            .map(|x| x.to_string())
+            .unwrap_or_else(|| "null".to_owned())
+    )
+    .ok();
+}
+
+fn thread_json(thread: ThreadInfo) -> String {
+    format!(
+        "{{\"id\":{},\"name\":{}}}",
+        thread.id,
+        thread
+            .name
+            .map(|x| {
+                registry::resolve_name(x)
+                    .map(|x| string_json(&x))
+                    .unwrap_or_else(|| x.value.to_string())
+            })
+            .unwrap_or_else(|| "null".to_owned())
+    )
+}
+
+fn location_json(location: SourceLocation) -> String {
+    format!(
+        "{{\"file\":{},\"line\":{}}}",
+        registry::resolve_name(location.file)
+            .map(|x| string_json(&x))
+            .unwrap_or_else(|| location.file.value.to_string()),
+        location.line
+    )
+}
+
+fn write_value_json(json: &mut String, value: &RecordValue) {
+    let (kind, value) = value_json(value);
+    write!(json, "\"type\":\"{kind}\",\"value\":{value}").ok();
+}
+
+fn value_json(value: &RecordValue) -> (&'static str, String) {
+    match value {
+        RecordValue::Instant(x) => ("Instant", x.to_string()),
+        RecordValue::UnixTime(x) => ("UnixTime", x.to_string()),
+        RecordValue::Utf8String(x) => ("Utf8String", string_json(x)),
+        RecordValue::I32(x) => ("I32", x.to_string()),
+        RecordValue::I64(x) => ("I64", x.to_string()),
+        RecordValue::I128(x) => ("I128", x.to_string()),
+        RecordValue::U32(x) => ("U32", x.to_string()),
+        RecordValue::U64(x) => ("U64", x.to_string()),
+        RecordValue::U128(x) => ("U128", x.to_string()),
+        RecordValue::F32(x) => ("F32", float_json(*x as f64)),
+        RecordValue::F64(x) => ("F64", float_json(*x)),
+        RecordValue::Bool(x) => ("Bool", x.to_string()),
+        RecordValue::Bytes(x) => ("Bytes", hex_json(x)),
+        RecordValue::Duration(x) => ("Duration", x.to_string()),
+        RecordValue::List(x) => {
+            let mut json = String::from("[");
+            for (i, value) in x.iter().enumerate() {
+                if i > 0 {
+                    json.push(',');
+                }
+                json.push('{');
+                write_value_json(&mut json, value);
+                json.push('}');
+            }
+            json.push(']');
+            ("List", json)
+        }
+    }
+}
+
+fn option_json(value: Option<impl ToString>) -> String {
+    value
+        .map(|x| x.to_string())
+        .unwrap_or_else(|| "null".to_owned())
+}
+
+fn hex_json(value: &[u8]) -> String {
+    let mut json = String::with_capacity(value.len() * 2 + 2);
+    json.push('"');
+    for byte in value {
+        write!(json, "{byte:02x}").ok();
+    }
+    json.push('"');
+    json
+}
+
+fn float_json(value: f64) -> String {
+    if value.is_finite() {
+        value.to_string()
+    } else {
+        "null".to_owned()
+    }
+}
+
+/// Format a string as a JSON string literal, escaping quotes, backslashes and control characters
+pub fn string_json(value: &str) -> String {
+    let mut json = String::with_capacity(value.len() + 2);
+    json.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                write!(json, "\\u{:04x}", c as u32).ok();
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
@@ -0,0 +1,47 @@
+//! Instrumentation tiers, so detailed instrumentation can be compiled away by the final binary.
+//!
+//! Library authors tag datapoints with a [Level], using [crate::Span::record_value_at_level] or the `level = ...`
+//! argument of the [crate::macros]. A binary caps the compiled level by enabling one of the `minimal`, `standard`
+//! or `verbose` cargo features of chronograph. When several are enabled, such as by different dependencies,
+//! the most restrictive wins. Without any of them, every level is compiled.
+//!
+//! # Example
+//! ```rust
+//! use chronograph::level::Level;
+//! use chronograph::Chronograph;
+//!
+//! let chronograph = Chronograph::builder().build();
+//! let mut span = chronograph.start_span();
+//! // always compiled
+//! span.record_value_at_level(Level::Minimal, "status_code", 200);
+//! // compiled away when the `minimal` or `standard` feature is enabled
+//! span.record_value_at_level(Level::Verbose, "cache_probe_count", 3);
+//! assert!(Level::Minimal.is_enabled());
+//! ```
+
+/// How detailed a datapoint is, from the fewest datapoints to the most
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Level {
+    /// Datapoints needed to tell whether an operation is healthy
+    Minimal,
+    /// Datapoints useful for routine performance analysis
+    Standard,
+    /// Fine-grained datapoints for deep investigations
+    Verbose,
+}
+
+/// The most detailed level compiled into this binary, selected by cargo features
+pub const MAX_LEVEL: Level = if cfg!(feature = "minimal") {
+    Level::Minimal
+} else if cfg!(feature = "standard") {
+    Level::Standard
+} else {
+    Level::Verbose
+};
+
+impl Level {
+    /// Whether datapoints of this level are compiled in, which is known at compile time
+    pub const fn is_enabled(self) -> bool {
+        self as u8 <= MAX_LEVEL as u8
+    }
+}
@@ -14,7 +14,17 @@
 //! - The start instant is a monotonic instant, accurate nanosecond timer elapsed from when the Cronograph was started.
 //! - The start instant can be used to calculate the duration of the span.
 //! - The end instant is a monotonic instant, accurate nanosecond timer elapsed from when the Cronograph was started.
+//! - The monotonic clock behind instants can be selected with [ChronographBuilder::with_clock_source], and is reported in the metadata of every batch.
 //! - User datapoints are typically recorded as "instant" time measurements, but they can also include metadata as simple types.
+//! - Child spans, started with [Span::child_span], also record the id of their parent span so span trees can be reconstructed.
+//! - All spans descending from the same root share a trace id. A trace can be continued across threads or processes with [Chronograph::start_span_in_trace].
+//! - Spans can be named with [Chronograph::start_named_span]. Names are stored as hashed ids, which can be resolved with the optional name [registry].
+//! - Static metadata, such as a tenant or endpoint, can be set as attributes with [Span::set_attribute], which are kept apart from the timed datapoints.
+//! - Spans can be linked to any number of other spans with [Span::add_link], such as a batch job referencing the requests it processed.
+//! - Each span has a kind, such as client or server, which can be set with [Chronograph::start_span_with_kind] for exporters to classify it.
+//! - Each span has a status, which is `Ok` unless an error is recorded with [Span::record_error].
+//! - Spans and datapoints can carry the source location that recorded them, by enabling [ChronographBuilder::with_source_locations].
+//! - Spans can carry the OS thread id and name that started them, by enabling [ChronographBuilder::with_thread_info].
 //!
 //!
 //! # Datapoints
@@ -29,6 +39,7 @@
 //! The [RecordValue] can be one of the following types:
 //! - Instant: A monotonic instant, accurate nanosecond timer elapsed from when the Cronograph was started.
 //! - UnixTime: A unix time, as nanoseconds since epoch.
+//! - Duration: An elapsed duration measured by the caller, as nanoseconds.
 //! - Utf8String: A string value formatted as UTF-8.
 //! - I32: A 32-bit signed integer.
 //! - I64: A 64-bit signed integer.
@@ -38,6 +49,11 @@
 //! - U128: A 128-bit unsigned integer.
 //! - F32: A 32-bit floating point number.
 //! - F64: A 64-bit floating point number.
+//! - Bool: A boolean flag.
+//! - Bytes: An arbitrary binary payload, such as a hash or an encoded message.
+//! - List: Multiple values, such as the latency of each retry. Elements may be of different types.
+//!
+//! Datapoints recorded on every sampled span, such as a region or build id, can be set once with [ChronographBuilder::with_default_datapoint].
 //!
 //!
 //! # Sampling
@@ -50,17 +66,41 @@
 //! The global chronograph is a singleton that can be used to record spans.
 //!
 //! It is initialized by calling the [init] function, and can be accessed with the [global] function.
+//! It can be overridden for the current thread, such as in tests or embedded plugins, with [with_scoped_global],
+//! which [global], [with_global], thread-local spans and the macros observe.
 //!
 //!
 //! # Macros
 //!
 //! The [macros] module provides macros for recording datapoints.
 //! - [macros::start_span] can be used to start a new thread-local span from the global chronograph.
+//! - [macros::child_span] can be used to start a child of the current thread-local span, restored when the child ends.
 //! - [macros::record_instant] can be used to record an instant datapoint to the current thread-local span.
+//! - [macros::lap] can be used to record the duration since the previous instant or lap.
 //! - [macros::record_unix_time] can be used to record a unix time datapoint.
 //! - [macros::record_value] can be used to record a value datapoint.
+//! - [macros::record_instant_if] and [macros::record_value_if] can be used to record only when a condition is true.
+//! - [macros::record_values] can be used to record several value datapoints at once.
+//! - [macros::record_error] can be used to mark the current thread-local span as failed with an error.
+//! - [macros::record_duration] can be used to record a duration datapoint from a [std::time::Duration].
 //! - [macros::end_span] can be used to end the current thread-local span.
 //! - [macros::take_span] can be used to take the current thread-local span.
+//! - [macros::set_span] can be used to set the current thread-local span, such as one taken on another thread.
+//! - [macros::datapoints] can be used to declare datapoint ids as constants and register their names in the [registry].
+//! - [macros::sampled] can be used to run a block only when the current thread-local span is sampled.
+//! - [macros::span_guard] can be used to start a thread-local span that ends when the returned guard is dropped.
+//! - [macros::defer_record] can be used to record an instant when the enclosing scope exits.
+//! - [macros::time_block] can be used to record a start and end instant around a block.
+//! - [macros::span] can be used to run a block in its own thread-local span, ended when the block exits.
+//! - [macros::instrument] can be used to run a function in its own thread-local span, named after the function.
+//!
+//! The record macros accept a leading `level = minimal|standard|verbose` argument, see [level].
+//! They also accept an explicit span before their other arguments, and [macros::start_span] an explicit [Chronograph]
+//! whose new span it returns, for code managing its own spans outside of the thread-local model.
+//!
+//! The `disabled` cargo feature compiles all instrumentation away without source changes:
+//! spans are never recorded, and the record macros, [macros::instrument], [macros::span] and [macros::time_block]
+//! expand to nothing but their arguments and blocks.
 //!
 //!
 //! # Thread-local Spans
@@ -71,10 +111,17 @@
 //! A thread-local span can be started with the [global] chronograph by calling the [start_threadlocal_span] function.
 //! You may alternative set it to any arbitraty span using the [set_threadlocal_span] function.
 //!
-//! The thread-local span can be accessed with the [get_threadlocal_span] function or with the included [macros].
+//! The thread-local span can be accessed with the [with_threadlocal_span] function or with the included [macros].
 //!
 //! [end_threadlocal_span] and [take_threadlocal_span] can be used to end/take the current thread-local span
 //!
+//! Thread-local spans form a stack: [start_threadlocal_child_span] pushes a child of the current thread-local span,
+//! and ending or taking it makes its parent current again, so nested instrumented functions don't clobber each other's spans.
+//!
+//! Async code can keep a span as the thread-local span across await points with [future::SpanFutureExt::in_span].
+//!
+//! [dump_current_spans] snapshots the thread-local spans of every thread, when enabled with [ChronographBuilder::with_span_dump].
+//!
 //!
 //! # Global Instance Example with Macros
 //!
@@ -169,30 +216,70 @@
 use std::{
     fmt::Debug,
     mem::take,
+    ops::{Deref, DerefMut},
     sync::{
         atomic::{AtomicU64, Ordering},
-        Arc,
+        Arc, Mutex, MutexGuard,
     },
-    time::{Instant, SystemTime},
+    time::{Duration, Instant, SystemTime},
 };
 
 use crate::{
-    processor::SpanProcessor,
-    recorder::SpanRecorder,
-    schema::{DatapointId, RecordData, RecordValue, SpanData},
+    clock::Clock,
+    defaults::DefaultDatapoints,
+    error::{ErrorHook, SpanError},
+    level::Level,
+    limiter::NameRateLimiter,
+    processor::{EncodeSpan, SpanEncoder, SpanProcessor},
+    propagation::{TraceParent, TraceParentError},
+    recorder::{ShutdownReport, SpanRecorder},
+    sched::RunDelay,
+    schema::{
+        Attribute, BatchMetadata, ClockSource, DatapointId, NameRateLimit, Outcome, PackedInstants,
+        RecordData, RecordValue, SamplingConfig, SourceLocation, SpanData, SpanKind, SpanLink,
+        SpanStatus, ThreadInfo, Unit,
+    },
+    staging::{Staging, StagingReceiver},
+    watchdog::{WatchHandle, Watchdog},
 };
 
+pub mod analysis;
+pub mod collector;
+pub mod error;
+pub mod future;
+#[cfg(feature = "introspect")]
+pub mod introspect;
+pub mod json;
+pub mod level;
 pub mod processor;
+pub mod propagation;
 pub mod recorder;
+pub mod registry;
 pub mod schema;
+pub mod stats;
+pub mod test;
 
+mod clock;
+mod defaults;
+mod fmt;
 mod global;
+mod limiter;
 mod local;
+#[doc(hidden)]
+pub mod macro_support;
+mod process;
+mod sched;
+mod staging;
+mod thread;
+mod watchdog;
 
-pub use global::{global, init};
+pub use global::{global, init, with_global, with_scoped_global};
 pub use local::{
-    end_threadlocal_span, get_threadlocal_span, set_threadlocal_span, start_threadlocal_span,
-    take_threadlocal_span,
+    defer_threadlocal_instant, dump_current_spans, end_threadlocal_span, enter_threadlocal_segment,
+    enter_threadlocal_span, get_threadlocal_span, is_threadlocal_span_sampled,
+    new_threadlocal_child_span, push_threadlocal_span, set_threadlocal_span,
+    start_threadlocal_child_span, start_threadlocal_span, take_threadlocal_span,
+    with_threadlocal_span, ThreadLocalSegment, ThreadLocalSpanGuard,
 };
 
 /// Re-export chronograph-macros as the macros module
@@ -202,8 +289,6 @@ pub use chronograph_macros as macros;
 #[derive(Debug)]
 pub struct Chronograph {
     context: Arc<ChronographContext>,
-    next_id: AtomicU64,
-    global_start_instant: Instant,
 }
 
 impl Chronograph {
@@ -212,24 +297,274 @@ impl Chronograph {
         ChronographBuilder {
             context: ChronographContext {
                 processors: Vec::new(),
+                encoders: Vec::new(),
                 recorder: SpanRecorder::NoOp(),
                 sample_rate: SampleRate::All,
+                // zero is the invalid span id of W3C trace context
+                next_id: AtomicU64::new(1),
+                clock: Clock::new(ClockSource::platform_default()),
+                register_names: false,
+                name_rate_limiter: None,
+                record_capacity: 0,
+                max_records: usize::MAX,
+                packed_instants: false,
+                min_record_duration: 0,
+                staging: None,
+                source_locations: false,
+                thread_info: false,
+                run_delay: false,
+                span_dump: false,
+                watchdog: None,
+                error_backtraces: false,
+                error_hook: None,
+                default_datapoints: DefaultDatapoints::default(),
+                partition_datapoint: None,
+                #[cfg(feature = "introspect")]
+                recent_spans: None,
             },
+            process_start_span: None,
+            staging_rx: None,
         }
     }
 
     /// Start a new span. It will be recorded when it's dropped from memory.
+    #[track_caller]
     pub fn start_span(&self) -> Span {
-        let span_id = self.next_id.fetch_add(1, Ordering::Relaxed);
-        Span {
-            sampled: self.context.sample_rate.sample(span_id),
-            global_start_instant: self.global_start_instant,
-            context: Arc::clone(&self.context),
+        let span_id = self.context.next_id.fetch_add(1, Ordering::Relaxed);
+        Span::new(
+            &self.context,
             span_id,
-            start_unix_time: SystemTime::now(),
-            start_instant: self.global_start_instant.elapsed().as_nanos() as u64,
-            records: Vec::new(),
+            self.context.sample_rate.sample(span_id),
+            None,
+            None,
+        )
+    }
+
+    /// Start a new span for work that began before the span could be created, such as a request received earlier.
+    ///
+    /// `start_instant_nanos` is in nanoseconds since the chronograph was built, see [Chronograph::instant_nanos].
+    #[track_caller]
+    pub fn start_span_at(&self, start_unix_time: SystemTime, start_instant_nanos: u64) -> Span {
+        let span_id = self.context.next_id.fetch_add(1, Ordering::Relaxed);
+        Span::new_at(
+            &self.context,
+            span_id,
+            self.context.sample_rate.sample(span_id),
+            None,
+            None,
+            start_unix_time,
+            start_instant_nanos,
+        )
+    }
+
+    /// Convert an [Instant], such as a request's receive time, to the nanoseconds of span instants.
+    ///
+    /// See [Chronograph::start_span_at].
+    pub fn instant_nanos(&self, instant: Instant) -> u64 {
+        instant
+            .saturating_duration_since(self.context.clock.start())
+            .as_nanos() as u64
+    }
+
+    /// Start a new span with an id received from another process or service, for cross-process correlation.
+    ///
+    /// The id is not checked for uniqueness, and the sampling decision is made from it like any other span id.
+    /// The id should not be zero, which is invalid in a [Span::traceparent].
+    #[track_caller]
+    pub fn start_span_with_id(&self, span_id: u64) -> Span {
+        Span::new(
+            &self.context,
+            span_id,
+            self.context.sample_rate.sample(span_id),
+            None,
+            None,
+        )
+    }
+
+    /// Start a new span continuing the trace of a W3C `traceparent` header, as a child of the remote span that sent it.
+    ///
+    /// The span is sampled when the remote span was, and otherwise by the sample rate. See [propagation].
+    #[track_caller]
+    pub fn start_span_from_traceparent(&self, traceparent: &str) -> Result<Span, TraceParentError> {
+        let traceparent: TraceParent = traceparent.parse()?;
+        let span_id = self.context.next_id.fetch_add(1, Ordering::Relaxed);
+        Ok(Span::new(
+            &self.context,
+            span_id,
+            traceparent.is_sampled() || self.context.sample_rate.sample(span_id),
+            Some(traceparent.span_id),
+            Some(traceparent.trace_id),
+        ))
+    }
+
+    /// Start a new span that is always recorded, regardless of the sample rate, such as for admin requests
+    #[track_caller]
+    pub fn start_sampled_span(&self) -> Span {
+        let span_id = self.context.next_id.fetch_add(1, Ordering::Relaxed);
+        Span::new(&self.context, span_id, true, None, None)
+    }
+
+    /// Start a new span with room for `capacity` records, so hot paths recording a known number of datapoints don't reallocate
+    #[track_caller]
+    pub fn start_span_with_capacity(&self, capacity: usize) -> Span {
+        let mut span = self.start_span();
+        if span.is_sampled() {
+            span.records.reserve(capacity);
+        }
+        span
+    }
+
+    /// Start a new root span that belongs to an existing trace, such as one received from another thread or process
+    #[track_caller]
+    pub fn start_span_in_trace(&self, trace_id: u128) -> Span {
+        let span_id = self.context.next_id.fetch_add(1, Ordering::Relaxed);
+        Span::new(
+            &self.context,
+            span_id,
+            self.context.sample_rate.sample(span_id),
+            None,
+            Some(trace_id),
+        )
+    }
+
+    /// Start `count` spans at once, with consecutive ids allocated by a single atomic add and a shared start time.
+    ///
+    /// This is cheaper than calling [Chronograph::start_span] in a tight loop when opening a span per item of a batch.
+    #[track_caller]
+    pub fn start_spans(&self, count: usize) -> Vec<Span> {
+        let first_span_id = self
+            .context
+            .next_id
+            .fetch_add(count as u64, Ordering::Relaxed);
+        let start_unix_time = SystemTime::now();
+        let start_instant = self.context.clock.elapsed_nanos();
+        // a loop rather than an iterator, so `#[track_caller]` sees the caller's location
+        let mut spans = Vec::with_capacity(count);
+        for span_id in first_span_id..first_span_id + count as u64 {
+            spans.push(Span::new_at(
+                &self.context,
+                span_id,
+                self.context.sample_rate.sample(span_id),
+                None,
+                None,
+                start_unix_time,
+                start_instant,
+            ));
+        }
+        spans
+    }
+
+    /// Start a new span with the given name, which is stored as a hashed [DatapointId] in [SpanData::name].
+    #[track_caller]
+    pub fn start_named_span(&self, name: &str) -> Span {
+        let context = &self.context;
+        let span_id = context.next_id.fetch_add(1, Ordering::Relaxed);
+        let name = context.name_id(name);
+        // the rate limit is applied before the span starts, so a limited span is never watched or given start datapoints
+        let sampled = !DISABLED
+            && context.sample_rate.sample(span_id)
+            && context
+                .name_rate_limiter
+                .as_ref()
+                .is_none_or(|x| x.try_acquire(name));
+        let mut span = Span::new(context, span_id, sampled, None, None);
+        span.name = Some(name);
+        span.watch.set_name(name);
+        span
+    }
+
+    /// Start a new span with the given [SpanKind], so exporters can tell client, server, and messaging spans apart.
+    #[track_caller]
+    pub fn start_span_with_kind(&self, kind: SpanKind) -> Span {
+        let mut span = self.start_span();
+        span.set_kind(kind);
+        span
+    }
+
+    /// Time `f` as a single root span with the given name, returning its value.
+    ///
+    /// The span is recorded straight through the recorder without the [Span] machinery, for the common case of timing
+    /// a function once. Its records are only allocated when default datapoints or run delays are configured.
+    #[track_caller]
+    pub fn time<R>(&self, name: &str, f: impl FnOnce() -> R) -> R {
+        let context = &self.context;
+        let span_id = context.next_id.fetch_add(1, Ordering::Relaxed);
+        let name = context.name_id(name);
+        let sampled = !DISABLED
+            && context.sample_rate.sample(span_id)
+            && context
+                .name_rate_limiter
+                .as_ref()
+                .is_none_or(|x| x.try_acquire(name));
+        if !sampled {
+            return f();
+        }
+        let location = match context.source_locations {
+            true => Some(caller_location()),
+            false => None,
+        };
+        let thread = match context.thread_info {
+            true => Some(thread::current_thread()),
+            false => None,
+        };
+        let run_delay = match context.run_delay {
+            true => RunDelay::now(),
+            false => None,
+        };
+        // collecting an empty iterator doesn't allocate
+        let start_datapoints: Vec<_> = context.default_datapoints.on_start().collect();
+        let start_unix_time = SystemTime::now();
+        let start_instant = context.clock.elapsed_nanos();
+        let result = f();
+        let end_instant = context.clock.elapsed_nanos();
+        if end_instant.saturating_sub(start_instant) < context.min_record_duration {
+            return result;
         }
+        let run_delay = run_delay
+            .and_then(|x| x.elapsed())
+            .map(|x| (DatapointId::from(RUN_DELAY), RecordValue::from(x)));
+        let records = start_datapoints
+            .into_iter()
+            .chain(context.default_datapoints.on_end())
+            .chain(run_delay)
+            .map(|(datapoint_id, value)| RecordData {
+                datapoint_id,
+                value,
+                unit: None,
+                location: None,
+                instant: None,
+            })
+            .collect();
+        context.record_span(SpanData {
+            span_id,
+            trace_id: new_trace_id(start_unix_time, span_id),
+            name: Some(name),
+            start_unix_time: unix_nanos(start_unix_time),
+            start_instant,
+            end_instant,
+            records,
+            location,
+            thread,
+            ..Default::default()
+        });
+        result
+    }
+
+    /// Start a new span from this chronograph as a child of the given span, which may have been started from another one.
+    /// See [Span::child_span].
+    #[track_caller]
+    pub fn start_child_span(&self, parent: &Span) -> Span {
+        parent.child_span_in(&self.context)
+    }
+
+    /// Flush and stop the underlying recorder, blocking until pending spans have been collected.
+    ///
+    /// Spans dropped after shutdown are not recorded.
+    pub fn shutdown(&self) -> ShutdownReport {
+        if let Some(staging) = &self.context.staging {
+            staging.flush();
+        }
+        self.context.recorder.shutdown()
     }
 }
 
@@ -237,6 +572,8 @@ impl Chronograph {
 #[derive(Debug)]
 pub struct ChronographBuilder {
     context: ChronographContext,
+    process_start_span: Option<process::BuildInfo>,
+    staging_rx: Option<StagingReceiver>,
 }
 
 impl ChronographBuilder {
@@ -252,18 +589,256 @@ impl ChronographBuilder {
         self
     }
 
+    /// Attach the output of `encoder` to each span it encodes as a [RecordValue::Bytes] record with the given datapoint id,
+    /// for downstream systems that expect a pre-encoded event embedded in the span.
+    ///
+    /// Encoders run in the order they were added, before any processor, so processors see the attached records.
+    pub fn with_encoder(
+        mut self,
+        datapoint_id: impl Into<DatapointId>,
+        encoder: impl EncodeSpan + 'static,
+    ) -> Self {
+        self.context.encoders.push(SpanEncoder {
+            datapoint_id: datapoint_id.into(),
+            encoder: Box::new(encoder),
+        });
+        self
+    }
+
     pub fn with_sample_rate(mut self, sample_rate: u64) -> Self {
         self.context.sample_rate = SampleRate::from(sample_rate);
         self
     }
 
+    /// Pre-allocate room for this many records in every sampled span, defaults to zero
+    pub fn with_record_capacity(mut self, record_capacity: usize) -> Self {
+        self.context.record_capacity = record_capacity;
+        self
+    }
+
+    /// Stage ended spans in a buffer owned by each thread, handing them to a processing thread in chunks of `chunk_size`.
+    ///
+    /// Ending a span then only costs an uncontended lock and a buffer write, while processors and the recorder run on the processing thread.
+    /// Spans staged by a thread are handed over when its buffer is full or the thread exits,
+    /// and [Chronograph::shutdown] flushes the buffers of every thread before shutting down the recorder.
+    pub fn with_staging(mut self, chunk_size: usize) -> Self {
+        let (staging, staging_rx) = Staging::new(chunk_size);
+        self.context.staging = Some(staging);
+        self.staging_rx = Some(staging_rx);
+        self
+    }
+
+    /// Limit every span to `max_records` records, so a runaway loop can't grow a span without bound.
+    ///
+    /// Further records are dropped and counted, and the count is recorded as a "records_overflowed" datapoint when the span ends.
+    pub fn with_max_records(mut self, max_records: usize) -> Self {
+        self.context.max_records = max_records;
+        self
+    }
+
+    /// Pack the plain instants of every sampled span by datapoint id while it is recorded, keeping a varint delta
+    /// rather than a full record for each, so spans recording the same instant many times, such as per iteration, take far less memory.
+    ///
+    /// Spans are recorded with the same records either way, see [schema::BatchEncoding::PackedRepeats] to also pack them when serialized.
+    /// Instants are not packed when [ChronographBuilder::with_source_locations] is enabled, since each has its own location.
+    pub fn with_packed_instants(mut self) -> Self {
+        self.context.packed_instants = true;
+        self
+    }
+
+    /// Silently drop spans shorter than `min_record_duration` when they end, so only slow operations are recorded.
+    ///
+    /// Spans taken with [Span::finish] are returned regardless of their duration.
+    pub fn with_min_record_duration(mut self, min_record_duration: Duration) -> Self {
+        self.context.min_record_duration = min_record_duration.as_nanos() as u64;
+        self
+    }
+
+    /// Limit each span name to `spans_per_second` sampled root spans, allowing bursts of up to `burst` spans.
+    ///
+    /// This is applied after [ChronographBuilder::with_sample_rate], so one noisy name can't consume the whole
+    /// sampling and export budget while rare names remain fully traced. Child spans follow their root's decision.
+    pub fn with_name_rate_limit(mut self, spans_per_second: u64, burst: u64) -> Self {
+        self.context.name_rate_limiter = Some(NameRateLimiter::new(spans_per_second, burst));
+        self
+    }
+
+    /// Keep a ring of the most recently recorded spans, served by an [introspect::IntrospectionServer]
+    #[cfg(feature = "introspect")]
+    pub fn with_introspection(mut self, recent_span_capacity: usize) -> Self {
+        self.context.recent_spans = Some(introspect::RecentSpans::new(recent_span_capacity));
+        self
+    }
+
+    /// Register span names in the global [registry] so they can be resolved from their hashed ids at analysis time
+    pub fn with_name_registry(mut self) -> Self {
+        self.context.register_names = true;
+        self
+    }
+
+    /// Attach the source location that started each span or recorded each datapoint, see [SourceLocation].
+    ///
+    /// Locations are captured with `#[track_caller]`, so they point at the calling code, including through the [macros].
+    /// Each file path is registered in the [registry], which makes recording more expensive, so this is disabled by default.
+    pub fn with_source_locations(mut self) -> Self {
+        self.context.source_locations = true;
+        self
+    }
+
+    /// Attach the OS thread id and name that started each span, see [ThreadInfo].
+    ///
+    /// The thread is resolved once per thread, and its name is registered in the [registry].
+    pub fn with_thread_info(mut self) -> Self {
+        self.context.thread_info = true;
+        self
+    }
+
+    /// Record how long each sampled span's thread spent waiting for a CPU as a "run_delay" duration datapoint.
+    ///
+    /// This tells a span that was slow from one that was starved by the scheduler. The delay is read from
+    /// `/proc/thread-self/schedstat` when the span starts and ends, so it is only recorded on Linux and Android,
+    /// and only for spans that end on the thread they started on.
+    pub fn with_run_delay(mut self) -> Self {
+        self.context.run_delay = true;
+        self
+    }
+
+    /// Publish a snapshot of thread-local spans from this chronograph whenever they are set or accessed,
+    /// so [dump_current_spans] can show what every thread is currently doing.
+    ///
+    /// Spans are cloned when their thread changes its thread-local span, and at most once per dump otherwise,
+    /// so this is meant for debugging rather than hot paths.
+    pub fn with_span_dump(mut self) -> Self {
+        self.context.span_dump = true;
+        self
+    }
+
+    /// Finalize sampled spans alive longer than `timeout`, so spans leaked by the application still reach the recorder.
+    ///
+    /// A watchdog thread records a timed-out span with a "timed_out" datapoint in place of its records, which stay
+    /// with the leaked span, and the span is not recorded again if it ends later.
+    pub fn with_watchdog(mut self, timeout: Duration) -> Self {
+        self.context.watchdog = Some(Watchdog::new(timeout));
+        self
+    }
+
+    /// Capture a backtrace whenever a span is marked as an error, see [Span::capture_backtrace].
+    ///
+    /// Resolving a backtrace is slow, so this is disabled by default.
+    pub fn with_error_backtraces(mut self) -> Self {
+        self.context.error_backtraces = true;
+        self
+    }
+
+    /// Select the monotonic clock that span instants are read from, which defaults to [ClockSource::platform_default].
+    ///
+    /// An unsupported clock falls back to the platform default. The clock in use is reported in [schema::BatchMetadata].
+    pub fn with_clock_source(mut self, clock_source: ClockSource) -> Self {
+        self.context.clock = Clock::new(clock_source);
+        self
+    }
+
+    /// Record a datapoint with the same value on every sampled span, such as a region or build id
+    pub fn with_default_datapoint(
+        mut self,
+        datapoint_id: impl Into<DatapointId>,
+        value: impl Into<RecordValue>,
+    ) -> Self {
+        self.context
+            .default_datapoints
+            .add_static(datapoint_id.into(), value.into());
+        self
+    }
+
+    /// Record a datapoint on every sampled span, evaluated by `f` as the span starts, such as a queue depth
+    pub fn with_start_datapoint<V: Into<RecordValue>>(
+        mut self,
+        datapoint_id: impl Into<DatapointId>,
+        f: impl Fn() -> V + Send + Sync + 'static,
+    ) -> Self {
+        self.context
+            .default_datapoints
+            .add_on_start(datapoint_id.into(), Box::new(move || f().into()));
+        self
+    }
+
+    /// Record a datapoint on every sampled span, evaluated by `f` as the span ends
+    pub fn with_end_datapoint<V: Into<RecordValue>>(
+        mut self,
+        datapoint_id: impl Into<DatapointId>,
+        f: impl Fn() -> V + Send + Sync + 'static,
+    ) -> Self {
+        self.context
+            .default_datapoints
+            .add_on_end(datapoint_id.into(), Box::new(move || f().into()));
+        self
+    }
+
+    /// Derive the partition key of spans without one from the attribute or datapoint with the given id,
+    /// so transports publish every span of the same entity, such as an order id, to the same partition.
+    ///
+    /// An attribute takes precedence over a datapoint, and the first datapoint with the id is used.
+    /// See [Span::set_partition_key] and [SpanData::partition].
+    pub fn with_partition_datapoint(mut self, datapoint_id: impl Into<DatapointId>) -> Self {
+        self.context.partition_datapoint = Some(datapoint_id.into());
+        self
+    }
+
+    /// Set a hook to be notified of [SpanError]s, such as recording to a span that has already ended.
+    ///
+    /// Without a hook, these errors panic in debug builds, so they are caught during development.
+    pub fn with_error_hook(
+        mut self,
+        error_hook: impl Fn(&SpanError) + Send + Sync + 'static,
+    ) -> Self {
+        self.context.error_hook = Some(Box::new(error_hook));
+        self
+    }
+
+    /// Record a single, always-sampled "process_start" span as soon as the [Chronograph] is built,
+    /// describing the build, host, and chronograph options so every recorded dataset is self-describing.
+    ///
+    /// The application's `version` and `git_hash` are taken from the caller, so they describe the application
+    /// rather than chronograph. The options recorded are the sampler and sample rate, the clock source, the number of processors,
+    /// the record capacity, whether packed instants, source locations, thread info and run delays are enabled,
+    /// and the staging chunk size, watchdog timeout, record limit, minimum record duration and name rate limit when they are set.
+    ///
+    /// The span is recorded regardless of [ChronographBuilder::with_min_record_duration].
+    ///
+    /// # Example
+    /// ```rust
+    /// use chronograph::Chronograph;
+    /// let chronograph = Chronograph::builder()
+    ///     .with_process_start_span(env!("CARGO_PKG_VERSION"), option_env!("GIT_HASH"))
+    ///     .build();
+    /// ```
+    pub fn with_process_start_span(mut self, version: &str, git_hash: Option<&str>) -> Self {
+        self.process_start_span = Some(process::BuildInfo {
+            version: version.to_owned(),
+            git_hash: git_hash.map(str::to_owned),
+        });
+        self
+    }
+
     /// Build the [Chronograph]
-    pub fn build(self) -> Chronograph {
-        Chronograph {
+    pub fn build(mut self) -> Chronograph {
+        self.context.clock = Clock::new(self.context.clock.source());
+        self.context
+            .recorder
+            .set_batch_metadata(self.context.batch_metadata());
+        let chronograph = Chronograph {
             context: Arc::new(self.context),
-            next_id: AtomicU64::new(0),
-            global_start_instant: Instant::now(),
+        };
+        if let Some(staging_rx) = self.staging_rx {
+            staging_rx.spawn(Arc::downgrade(&chronograph.context));
         }
+        if let Some(watchdog) = &chronograph.context.watchdog {
+            watchdog.spawn(Arc::downgrade(&chronograph.context));
+        }
+        if let Some(build_info) = self.process_start_span {
+            process::record_process_start_span(&chronograph, build_info);
+        }
+        chronograph
     }
 }
 
@@ -271,90 +846,1066 @@ impl ChronographBuilder {
 #[derive(Debug, Clone)]
 pub struct Span {
     sampled: bool,
-    global_start_instant: Instant,
+    ended: bool,
+    clock: Clock,
     context: Arc<ChronographContext>,
     span_id: u64,
+    parent_span_id: Option<u64>,
+    trace_id: u128,
+    name: Option<DatapointId>,
+    kind: SpanKind,
     start_unix_time: SystemTime,
     start_instant: u64,
+    /// The unix time and instant of the earliest span combined with [Span::merge], when it started before this span.
+    /// Kept apart from `start_instant`, which packed instants and laps are measured from.
+    merged_start: Option<(i64, u64)>,
+    /// The latest end instant of the spans combined with [Span::merge]
+    merged_end_instant: u64,
+    /// The instant that the next [Span::record_lap] is measured from
+    lap_instant: u64,
+    location: Option<SourceLocation>,
+    thread: Option<ThreadInfo>,
+    /// The run delay of the thread when the span started, see [ChronographBuilder::with_run_delay]
+    run_delay: Option<RunDelay>,
+    status: SpanStatus,
+    backtrace: Option<String>,
+    deadline: Option<Duration>,
     records: Vec<RecordData>,
+    /// Instants kept out of `records`, see [ChronographBuilder::with_packed_instants]
+    packed: PackedInstants,
+    /// Records dropped after reaching [ChronographBuilder::with_max_records]
+    records_overflowed: u64,
+    attributes: Vec<Attribute>,
+    links: Vec<SpanLink>,
+    partition_key: Option<u64>,
+    outcome: Option<Outcome>,
+    /// The span's registration with [ChronographBuilder::with_watchdog]
+    watch: WatchHandle,
+    on_drop: DropCallbacks,
 }
 
 impl Span {
-    pub fn record_instant(&mut self, datapoint_id: impl Into<DatapointId>) -> &mut Self {
-        if self.sampled {
-            self.record_value(
-                datapoint_id,
-                RecordValue::Instant(self.global_start_instant.elapsed().as_nanos() as u64),
+    #[track_caller]
+    fn new(
+        context: &Arc<ChronographContext>,
+        span_id: u64,
+        sampled: bool,
+        parent_span_id: Option<u64>,
+        trace_id: Option<u128>,
+    ) -> Self {
+        Self::new_at(
+            context,
+            span_id,
+            sampled,
+            parent_span_id,
+            trace_id,
+            SystemTime::now(),
+            context.clock.elapsed_nanos(),
+        )
+    }
+
+    #[track_caller]
+    fn new_at(
+        context: &Arc<ChronographContext>,
+        span_id: u64,
+        sampled: bool,
+        parent_span_id: Option<u64>,
+        trace_id: Option<u128>,
+        start_unix_time: SystemTime,
+        start_instant: u64,
+    ) -> Self {
+        let trace_id = trace_id.unwrap_or_else(|| new_trace_id(start_unix_time, span_id));
+        let mut span = Self {
+            sampled: false,
+            ended: false,
+            clock: context.clock,
+            context: Arc::clone(context),
+            span_id,
+            parent_span_id,
+            trace_id,
+            name: None,
+            kind: SpanKind::Internal,
+            start_unix_time,
+            start_instant,
+            merged_start: None,
+            merged_end_instant: 0,
+            lap_instant: start_instant,
+            location: None,
+            thread: None,
+            run_delay: None,
+            status: SpanStatus::Ok,
+            backtrace: None,
+            deadline: None,
+            records: Vec::new(),
+            packed: PackedInstants::default(),
+            records_overflowed: 0,
+            attributes: Vec::new(),
+            links: Vec::new(),
+            partition_key: None,
+            outcome: None,
+            watch: WatchHandle::default(),
+            on_drop: DropCallbacks::default(),
+        };
+        if sampled {
+            span.start_sampling();
+        }
+        span
+    }
+
+    /// Mark the span as sampled, capturing what a sampled span records beyond its records,
+    /// either when it starts or when it is forced to be sampled
+    #[track_caller]
+    fn start_sampling(&mut self) {
+        if DISABLED {
+            return;
+        }
+        self.sampled = true;
+        let context = Arc::clone(&self.context);
+        if context.source_locations {
+            self.location = Some(caller_location());
+        }
+        if context.thread_info {
+            self.thread = Some(thread::current_thread());
+        }
+        if context.run_delay {
+            self.run_delay = RunDelay::now();
+        }
+        self.records.reserve(context.record_capacity);
+        if let Some(watchdog) = &context.watchdog {
+            self.watch = watchdog.watch(
+                self.span_id,
+                self.parent_span_id,
+                self.trace_id,
+                unix_nanos(self.start_unix_time),
+                self.start_instant,
             );
+        }
+        for (datapoint_id, value) in context.default_datapoints.on_start() {
+            self.record_value_no_sampling(datapoint_id, value, None);
+        }
+    }
+
+    /// Start a new span from the same [Chronograph], recorded with this span as its parent.
+    ///
+    /// The child inherits this span's trace id and sampling decision, so sampled trees are always complete.
+    /// It also inherits the partition key, see [Span::set_partition_key].
+    #[track_caller]
+    pub fn child_span(&self) -> Span {
+        self.child_span_in(&self.context)
+    }
+
+    /// Start a child of this span recorded by the chronograph of `context`
+    #[track_caller]
+    fn child_span_in(&self, context: &Arc<ChronographContext>) -> Span {
+        let span_id = context.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut span = Span::new(
+            context,
+            span_id,
+            self.sampled,
+            Some(self.span_id),
+            Some(self.trace_id),
+        );
+        span.partition_key = self.partition_key;
+        span
+    }
+
+    /// The id of this span, unique within its [Chronograph]
+    pub fn span_id(&self) -> u64 {
+        self.span_id
+    }
+
+    /// The id of the trace this span belongs to, shared by all of its descendants
+    pub fn trace_id(&self) -> u128 {
+        self.trace_id
+    }
+
+    /// The id of the span this span was started from, if any
+    pub fn parent_span_id(&self) -> Option<u64> {
+        self.parent_span_id
+    }
+
+    /// The W3C `traceparent` of this span, to send to another service as the [propagation::TRACEPARENT_HEADER] header
+    pub fn traceparent(&self) -> TraceParent {
+        TraceParent {
+            trace_id: self.trace_id,
+            span_id: self.span_id,
+            flags: self.sampled as u8,
+        }
+    }
+
+    /// Whether this span will be recorded. Records to unsampled spans are discarded.
+    ///
+    /// Spans are never sampled with the `disabled` feature, which lets the compiler remove every record.
+    #[inline]
+    pub fn is_sampled(&self) -> bool {
+        !DISABLED && self.sampled
+    }
+
+    /// Guarantee this span is recorded regardless of the sample rate, such as on an error path.
+    ///
+    /// Records made before this call to an unsampled span were discarded, and child spans started before it keep their own decision.
+    /// The span is otherwise initialized as if it had been sampled when it started, except that the default start datapoints,
+    /// source location, thread info and run delay are captured when and where this is called.
+    #[track_caller]
+    pub fn force_sample(&mut self) -> &mut Self {
+        if !self.sampled && !self.ended {
+            self.start_sampling();
+        }
+        self
+    }
+
+    /// The monotonic instant at which this span started
+    pub fn start_instant(&self) -> Instant {
+        self.clock.start() + Duration::from_nanos(self.start_instant)
+    }
+
+    /// Convert an [Instant], such as one captured before it could be recorded, to the nanoseconds of span instants.
+    ///
+    /// See [Span::record_instant_at] and [Span::record_value_at].
+    pub fn instant_nanos(&self, instant: Instant) -> u64 {
+        instant
+            .saturating_duration_since(self.clock.start())
+            .as_nanos() as u64
+    }
+
+    /// Time elapsed since this span started, such as to check a timeout
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_nanos(
+            self.clock
+                .elapsed_nanos()
+                .saturating_sub(self.start_instant),
+        )
+    }
+
+    /// Set the name of this span, replacing any existing name
+    ///
+    /// When [ChronographBuilder::with_name_rate_limit] is enabled, naming a sampled root span for the first time
+    /// takes a token from the rate limit of that name, and the span is no longer sampled when none are left.
+    pub fn set_name(&mut self, name: &str) -> &mut Self {
+        let name = self.context.name_id(name);
+        if let Some(limiter) = &self.context.name_rate_limiter {
+            if self.is_sampled()
+                && self.name.is_none()
+                && self.parent_span_id.is_none()
+                && !limiter.try_acquire(name)
+            {
+                self.stop_sampling();
+            }
+        }
+        self.name = Some(name);
+        self.watch.set_name(name);
+        self
+    }
+
+    /// Undo [Span::start_sampling], deregistering the span from the watchdog and discarding what it recorded so far
+    fn stop_sampling(&mut self) {
+        self.sampled = false;
+        self.watch.finalize();
+        self.location = None;
+        self.thread = None;
+        self.run_delay = None;
+        self.records = Vec::new();
+        self.packed = PackedInstants::default();
+        self.records_overflowed = 0;
+    }
+
+    /// Set the kind of this span, which defaults to [SpanKind::Internal]
+    pub fn set_kind(&mut self, kind: SpanKind) -> &mut Self {
+        self.kind = kind;
+        self
+    }
+
+    /// Set the key transports use to publish this span, so spans with the same key keep their order downstream,
+    /// such as all spans of one order id landing in one partition. Child spans started after this call inherit it.
+    ///
+    /// The key is derived with [RecordValue::partition_key], and is ignored for lists.
+    pub fn set_partition_key(&mut self, key: impl Into<RecordValue>) -> &mut Self {
+        self.partition_key = key.into().partition_key();
+        self
+    }
+
+    #[track_caller]
+    pub fn record_instant(&mut self, datapoint_id: impl Into<DatapointId>) -> &mut Self {
+        if self.is_sampled() {
+            let instant = self.clock.elapsed_nanos();
+            self.lap_instant = instant;
+            self.record_instant_no_sampling(datapoint_id.into(), instant);
         };
         self
     }
 
+    /// Share this span between threads, such as workers of a fan-out, which record into it through a [SpanHandle].
+    ///
+    /// The span is recorded when the last handle is dropped.
+    pub fn into_handle(self) -> SpanHandle {
+        SpanHandle::from(self)
+    }
+
+    /// Enter a segment of the span, returning a guard that records when the segment ends as it is dropped.
+    ///
+    /// A segment is recorded as a pair of instants with the same datapoint id, the first marking its start and the second its end.
+    /// The guard dereferences to the span, so datapoints can still be recorded while it is held.
+    #[track_caller]
+    pub fn enter_segment(&mut self, datapoint_id: impl Into<DatapointId>) -> SpanSegment<'_> {
+        let datapoint_id = datapoint_id.into();
+        self.record_instant(datapoint_id);
+        SpanSegment {
+            span: self,
+            datapoint_id,
+        }
+    }
+
+    /// Record the duration since the previous instant recorded with [Span::record_instant] or [Span::record_lap],
+    /// or since the span started, which makes timing each iteration of a loop or each phase of an operation trivial.
+    #[track_caller]
+    pub fn record_lap(&mut self, datapoint_id: impl Into<DatapointId>) -> &mut Self {
+        if self.is_sampled() {
+            let instant = self.clock.elapsed_nanos();
+            let lap = instant.saturating_sub(self.lap_instant);
+            self.lap_instant = instant;
+            self.record_value(datapoint_id, RecordValue::Duration(lap));
+        }
+        self
+    }
+
+    /// Record an instant that was measured earlier, such as in an interrupt handler, with its true timestamp.
+    ///
+    /// The instant is in nanoseconds since the chronograph was built, see [Span::instant_nanos].
+    /// Unlike [Span::record_instant], it does not restart the lap measured by [Span::record_lap].
+    #[track_caller]
+    pub fn record_instant_at(
+        &mut self,
+        datapoint_id: impl Into<DatapointId>,
+        instant_nanos: u64,
+    ) -> &mut Self {
+        if self.is_sampled() {
+            self.record_instant_no_sampling(datapoint_id.into(), instant_nanos);
+        }
+        self
+    }
+
+    /// Record a value that was measured earlier, such as from a hardware counter, along with the instant it was measured at.
+    ///
+    /// The instant is in nanoseconds since the chronograph was built, see [Span::instant_nanos].
+    #[track_caller]
+    pub fn record_value_at(
+        &mut self,
+        datapoint_id: impl Into<DatapointId>,
+        value: impl Into<RecordValue>,
+        instant_nanos: u64,
+    ) -> &mut Self {
+        if self.is_sampled() {
+            if let Some(record) = self.record_value_no_sampling(datapoint_id, value, None) {
+                record.instant = Some(instant_nanos);
+            }
+        }
+        self
+    }
+
+    #[track_caller]
     pub fn record_unix_time(&mut self, datapoint_id: impl Into<DatapointId>) -> &mut Self {
-        if self.sampled {
+        if self.is_sampled() {
             self.record_value_no_sampling(
                 datapoint_id,
-                RecordValue::UnixTime(
-                    self.start_unix_time
-                        .duration_since(SystemTime::UNIX_EPOCH)
-                        .unwrap_or_default()
-                        .as_nanos() as i64,
-                ),
+                RecordValue::UnixTime(unix_nanos(self.start_unix_time)),
+                None,
             );
         }
         self
     }
 
+    #[track_caller]
     pub fn record_value(
         &mut self,
         datapoint_id: impl Into<DatapointId>,
         value: impl Into<RecordValue>,
     ) -> &mut Self {
-        if self.sampled {
-            self.record_value_no_sampling(datapoint_id, value);
+        if self.is_sampled() {
+            self.record_value_no_sampling(datapoint_id, value, None);
+        }
+        self
+    }
+
+    /// Record a value datapoint only when its [level::Level] is compiled in, see [level]
+    #[track_caller]
+    #[inline]
+    pub fn record_value_at_level(
+        &mut self,
+        level: Level,
+        datapoint_id: impl Into<DatapointId>,
+        value: impl Into<RecordValue>,
+    ) -> &mut Self {
+        if level.is_enabled() {
+            self.record_value(datapoint_id, value);
+        }
+        self
+    }
+
+    /// Set an attribute of the span, replacing any existing value for the same key.
+    ///
+    /// Attributes are stored separately from the timed records, and are meant for static metadata such as a tenant, endpoint, or version.
+    pub fn set_attribute(
+        &mut self,
+        key: impl Into<DatapointId>,
+        value: impl Into<RecordValue>,
+    ) -> &mut Self {
+        if !self.is_sampled() {
+            return self;
+        }
+        let key = key.into();
+        if self.ended {
+            self.report_error(SpanError::RecordAfterEnd {
+                span_id: self.span_id,
+                datapoint_id: key,
+            });
+            return self;
+        }
+        let value = value.into();
+        match self.attributes.iter_mut().find(|x| x.key == key) {
+            Some(attribute) => attribute.value = value,
+            None => self.attributes.push(Attribute { key, value }),
+        }
+        self
+    }
+
+    /// Link this span to another span, such as each of the requests processed by a batch job.
+    ///
+    /// Unlike a parent, linked spans may belong to other traces, and a span may have any number of links.
+    pub fn add_link(&mut self, span_id: u64, trace_id: u128) -> &mut Self {
+        if self.is_sampled() && !self.ended {
+            self.links.push(SpanLink { span_id, trace_id });
+        }
+        self
+    }
+
+    /// Set the status of the span, which defaults to [SpanStatus::Ok]
+    ///
+    /// When [ChronographBuilder::with_error_backtraces] is enabled, marking the span as an error also captures a backtrace.
+    pub fn set_status(&mut self, status: SpanStatus) -> &mut Self {
+        if matches!(status, SpanStatus::Error(_))
+            && self.context.error_backtraces
+            && self.backtrace.is_none()
+        {
+            self.capture_backtrace();
+        }
+        self.status = status;
+        self
+    }
+
+    /// Set the standardized [Outcome] of the operation, replacing any previous one.
+    ///
+    /// It is independent of the [SpanStatus], so a server error can also be marked with [Span::record_error].
+    pub fn set_outcome(&mut self, outcome: Outcome) -> &mut Self {
+        self.outcome = Some(outcome);
+        self
+    }
+
+    /// Capture a resolved backtrace of the current call stack and attach it to the span, replacing any previous one.
+    ///
+    /// This is expensive, and is skipped for unsampled spans.
+    pub fn capture_backtrace(&mut self) -> &mut Self {
+        if self.is_sampled() {
+            self.backtrace = Some(std::backtrace::Backtrace::force_capture().to_string());
+        }
+        self
+    }
+
+    /// Mark the span as failed, storing the error and its chain of sources as the status message
+    pub fn record_error(&mut self, error: &dyn std::error::Error) -> &mut Self {
+        let mut message = error.to_string();
+        let mut source = error.source();
+        while let Some(error) = source {
+            message.push_str(": ");
+            message.push_str(&error.to_string());
+            source = error.source();
         }
+        self.set_status(SpanStatus::Error(Some(message)))
+    }
+
+    /// Set a time budget for the span, measured from its start.
+    ///
+    /// When the span ends, a "deadline_exceeded" boolean datapoint is recorded,
+    /// along with a "deadline_overrun" duration datapoint of how far past the deadline it ended.
+    pub fn set_deadline(&mut self, deadline: Duration) -> &mut Self {
+        self.deadline = Some(deadline);
         self
     }
 
+    /// Register a callback to run exactly once when the span ends, before its data is finalized.
+    ///
+    /// This is useful to record final datapoints, such as totals or a final state, without repeating bookkeeping at every return point.
+    /// Callbacks run in the order they were registered, and are not carried over to clones of the span.
+    pub fn on_drop(&mut self, callback: impl FnOnce(&mut Span) + Send + 'static) -> &mut Self {
+        self.on_drop.on_drop.push(Box::new(callback));
+        self
+    }
+
+    /// Register a callback to be notified with the span's data once it is finalized, without a global processor.
+    ///
+    /// Callbacks run after [Span::on_drop] callbacks, in the order they were registered, and before the span is recorded.
+    /// They are not run for unsampled or discarded spans, nor for spans dropped by [ChronographBuilder::with_min_record_duration],
+    /// and are not carried over to clones of the span.
+    pub fn on_end(&mut self, callback: impl FnOnce(&SpanData) + Send + 'static) -> &mut Self {
+        self.on_drop.on_end.push(Box::new(callback));
+        self
+    }
+
+    /// Record a value that is only computed when the span is sampled, skipping expensive formatting or serialization otherwise
+    #[track_caller]
+    pub fn record_lazy<V: Into<RecordValue>>(
+        &mut self,
+        datapoint_id: impl Into<DatapointId>,
+        value: impl FnOnce() -> V,
+    ) -> &mut Self {
+        if self.is_sampled() {
+            self.record_value_no_sampling(datapoint_id, value(), None);
+        }
+        self
+    }
+
+    /// Run `f` and record how long it took as a duration datapoint, returning its value
+    #[track_caller]
+    pub fn time<R>(&mut self, datapoint_id: impl Into<DatapointId>, f: impl FnOnce() -> R) -> R {
+        let start = self.clock.elapsed_nanos();
+        let result = f();
+        if self.is_sampled() {
+            let duration = self.clock.elapsed_nanos().saturating_sub(start);
+            self.record_value_no_sampling(datapoint_id, RecordValue::Duration(duration), None);
+        }
+        result
+    }
+
+    /// Fold records collected independently, such as in a helper struct or another thread, into this span.
+    ///
+    /// The records are appended as-is, so their instants must come from the same [Chronograph].
+    /// They count towards [ChronographBuilder::with_max_records] like any other record.
+    pub fn merge_from(&mut self, other_records: Vec<RecordData>) -> &mut Self {
+        if !self.is_sampled() {
+            return self;
+        }
+        if self.ended {
+            if let Some(record) = other_records.first() {
+                self.report_error(SpanError::RecordAfterEnd {
+                    span_id: self.span_id,
+                    datapoint_id: record.datapoint_id,
+                });
+            }
+            return self;
+        }
+        let room = self.context.max_records.saturating_sub(self.record_count());
+        if other_records.len() > room {
+            self.records_overflowed += (other_records.len() - room) as u64;
+        }
+        self.records.extend(other_records.into_iter().take(room));
+        self
+    }
+
+    /// Combine a related span, such as a retried attempt taken with [Span::snapshot], into this span,
+    /// like [SpanData::merge] does for recorded spans.
+    ///
+    /// Its records are merged with [Span::merge_from] and its links are appended.
+    /// Its attributes are added unless this span already has an attribute with the same key,
+    /// and its outcome is kept unless this span already has one.
+    /// The recorded span is widened to cover both time ranges, while [Span::elapsed] still measures this span.
+    pub fn merge(&mut self, other: SpanData) -> &mut Self {
+        self.merge_from(other.records);
+        if self.is_sampled() && !self.ended {
+            for attribute in other.attributes {
+                if !self.attributes.iter().any(|x| x.key == attribute.key) {
+                    self.attributes.push(attribute);
+                }
+            }
+            self.links.extend(other.links);
+            self.outcome = self.outcome.or(other.outcome);
+            let merged_start_instant = self.merged_start.map_or(self.start_instant, |x| x.1);
+            if other.start_instant < merged_start_instant {
+                self.merged_start = Some((other.start_unix_time, other.start_instant));
+            }
+            self.merged_end_instant = self.merged_end_instant.max(other.end_instant);
+        }
+        self
+    }
+
+    /// Add to a counter datapoint, which is recorded once per span with the [Unit::Count] unit,
+    /// rather than pushing a new record for every increment of a high-frequency counter
+    #[track_caller]
+    pub fn record_add(&mut self, datapoint_id: impl Into<DatapointId>, n: u64) -> &mut Self {
+        if !self.is_sampled() {
+            return self;
+        }
+        let datapoint_id = datapoint_id.into();
+        if !self.ended {
+            let counter = self.records.iter_mut().rev().find_map(|x| match x {
+                RecordData {
+                    datapoint_id: id,
+                    value: RecordValue::U64(count),
+                    unit: Some(Unit::Count),
+                    ..
+                } if *id == datapoint_id => Some(count),
+                _ => None,
+            });
+            if let Some(count) = counter {
+                *count = count.saturating_add(n);
+                return self;
+            }
+        }
+        self.record_value_no_sampling(datapoint_id, n, Some(Unit::Count));
+        self
+    }
+
+    /// Record an elapsed duration that was measured by the caller
+    #[track_caller]
+    pub fn record_duration(
+        &mut self,
+        datapoint_id: impl Into<DatapointId>,
+        duration: Duration,
+    ) -> &mut Self {
+        self.record_value(datapoint_id, duration)
+    }
+
+    /// Record a numeric value annotated with its [Unit], so exporters can label it without heuristics
+    #[track_caller]
+    pub fn record_value_with_unit(
+        &mut self,
+        datapoint_id: impl Into<DatapointId>,
+        value: impl Into<RecordValue>,
+        unit: Unit,
+    ) -> &mut Self {
+        if self.is_sampled() {
+            self.record_value_no_sampling(datapoint_id, value, Some(unit));
+        }
+        self
+    }
+
+    #[track_caller]
     fn record_value_no_sampling(
         &mut self,
         datapoint_id: impl Into<DatapointId>,
         value: impl Into<RecordValue>,
-    ) {
+        unit: Option<Unit>,
+    ) -> Option<&mut RecordData> {
+        if self.ended {
+            self.report_error(SpanError::RecordAfterEnd {
+                span_id: self.span_id,
+                datapoint_id: datapoint_id.into(),
+            });
+            return None;
+        }
+        if self.record_count() >= self.context.max_records {
+            self.records_overflowed += 1;
+            return None;
+        }
+        let location = match self.context.source_locations {
+            true => Some(caller_location()),
+            false => None,
+        };
         self.records.push(RecordData {
             datapoint_id: datapoint_id.into(),
             value: value.into(),
+            unit,
+            location,
+            instant: None,
         });
+        self.records.last_mut()
     }
-}
 
-impl Drop for Span {
-    fn drop(&mut self) {
-        if !self.sampled {
+    /// Record an instant, packing it when [ChronographBuilder::with_packed_instants] is enabled
+    #[track_caller]
+    fn record_instant_no_sampling(&mut self, datapoint_id: DatapointId, instant: u64) {
+        if self.context.packed_instants
+            && !self.context.source_locations
+            && !self.ended
+            && self.record_count() < self.context.max_records
+        {
+            self.packed.push(
+                datapoint_id,
+                instant,
+                self.record_count(),
+                self.start_instant,
+            );
             return;
         }
-        let span_data = SpanData {
+        self.record_value_no_sampling(datapoint_id, RecordValue::Instant(instant), None);
+    }
+
+    /// The number of records, including packed instants
+    fn record_count(&self) -> usize {
+        self.records.len() + self.packed.len()
+    }
+}
+
+impl Span {
+    /// End the span now, recording it immediately rather than when it is dropped.
+    ///
+    /// The span is frozen afterwards. Recording to it discards the record and reports it to [ChronographBuilder::with_error_hook],
+    /// or panics in debug builds without a hook.
+    pub fn end(&mut self) {
+        if let Some(span_data) = self.finalize(true) {
+            self.context.record_span(span_data);
+        }
+    }
+
+    /// End the span now, handing its data to the caller instead of the configured processors and recorder.
+    ///
+    /// This is useful to embed span data in a response or feed it to a custom pipeline.
+    /// Returns `None` when the span was not sampled.
+    pub fn finish(mut self) -> Option<SpanData> {
+        self.finalize(false)
+    }
+
+    /// Run the drop callbacks and freeze the span, taking its data when it was sampled,
+    /// and when `min_duration` is set, long enough for [ChronographBuilder::with_min_record_duration]
+    fn finalize(&mut self, min_duration: bool) -> Option<SpanData> {
+        if self.ended {
+            return None;
+        }
+        if !self.watch.finalize() {
+            // the watchdog already recorded the span as timed out
+            self.on_drop = DropCallbacks::default();
+            self.ended = true;
+            return None;
+        }
+        for callback in take(&mut self.on_drop.on_drop) {
+            callback(self);
+        }
+        if self.is_sampled() {
+            let context = Arc::clone(&self.context);
+            for (datapoint_id, value) in context.default_datapoints.on_end() {
+                self.record_value_no_sampling(datapoint_id, value, None);
+            }
+        }
+        if let Some(deadline) = self.deadline.take() {
+            let overrun = self.elapsed().saturating_sub(deadline);
+            self.record_value(DEADLINE_EXCEEDED, !overrun.is_zero());
+            if !overrun.is_zero() {
+                self.record_duration(DEADLINE_OVERRUN, overrun);
+            }
+        }
+        if let Some(run_delay) = self.run_delay.take().and_then(|x| x.elapsed()) {
+            self.record_duration(RUN_DELAY, run_delay);
+        }
+        self.ended = true;
+        let on_end = take(&mut self.on_drop.on_end);
+        if !self.is_sampled() {
+            return None;
+        }
+        let records = take(&mut self.packed).unpack(take(&mut self.records), self.start_instant);
+        let (status, attributes, links, backtrace) = (
+            take(&mut self.status),
+            take(&mut self.attributes),
+            take(&mut self.links),
+            take(&mut self.backtrace),
+        );
+        let span_data = self.span_data(records, status, attributes, links, backtrace);
+        if min_duration && !self.context.meets_min_duration(&span_data) {
+            return None;
+        }
+        for callback in on_end {
+            callback(&span_data);
+        }
+        Some(span_data)
+    }
+
+    /// An estimate of the bytes the span would take if it ended now, see [SpanData::estimated_serialized_size]
+    pub fn current_size(&self) -> usize {
+        schema::estimated_serialized_size(
+            &self.records,
+            &self.attributes,
+            &self.links,
+            &self.status,
+            self.backtrace.as_deref(),
+        ) + self.packed.estimated_serialized_size()
+    }
+
+    /// A copy of the span's current state, as it would be recorded if it ended now, without ending it.
+    ///
+    /// This is useful to report the progress of long-running spans periodically. Callbacks registered with
+    /// [Span::on_drop] and end-of-span datapoints are not included, since they only run when the span ends.
+    pub fn snapshot(&self) -> SpanData {
+        self.span_data(
+            self.packed.unpack(self.records.clone(), self.start_instant),
+            self.status.clone(),
+            self.attributes.clone(),
+            self.links.clone(),
+            self.backtrace.clone(),
+        )
+    }
+
+    /// Build the [SpanData] of the span as of now from its records and the parts that are moved or cloned out of it,
+    /// shared by [Span::finalize] and [Span::snapshot]
+    fn span_data(
+        &self,
+        mut records: Vec<RecordData>,
+        status: SpanStatus,
+        attributes: Vec<Attribute>,
+        links: Vec<SpanLink>,
+        backtrace: Option<String>,
+    ) -> SpanData {
+        if self.records_overflowed > 0 {
+            records.push(RecordData {
+                datapoint_id: DatapointId::from(RECORDS_OVERFLOWED),
+                value: RecordValue::U64(self.records_overflowed),
+                unit: Some(Unit::Count),
+                location: None,
+                instant: None,
+            });
+        }
+        let (start_unix_time, start_instant) = self
+            .merged_start
+            .unwrap_or((unix_nanos(self.start_unix_time), self.start_instant));
+        SpanData {
             span_id: self.span_id,
-            start_unix_time: self
-                .start_unix_time
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap_or_default()
-                .as_nanos() as i64,
-            start_instant: self.start_instant,
-            end_instant: self.global_start_instant.elapsed().as_nanos() as u64,
-            records: take(&mut self.records),
-        };
-        for post_processor in self.context.processors.iter() {
-            post_processor.post_process_span(&span_data);
+            parent_span_id: self.parent_span_id,
+            trace_id: self.trace_id,
+            name: self.name,
+            kind: self.kind,
+            start_unix_time,
+            start_instant,
+            end_instant: self.clock.elapsed_nanos().max(self.merged_end_instant),
+            status,
+            records,
+            attributes,
+            links,
+            location: self.location,
+            thread: self.thread,
+            backtrace,
+            partition_key: self.partition_key,
+            outcome: self.outcome,
         }
-        self.context.recorder.record_span(span_data);
+    }
+
+    /// Consume the span without recording it.
+    ///
+    /// No processor or recorder will see the span, and callbacks registered with [Span::on_drop] or [Span::on_end] are not run.
+    pub fn discard(mut self) {
+        self.watch.finalize();
+        self.on_drop = DropCallbacks::default();
+        self.ended = true;
+    }
+
+    fn report_error(&self, error: SpanError) {
+        match &self.context.error_hook {
+            Some(error_hook) => error_hook(&error),
+            None if cfg!(debug_assertions) => panic!("{error}"),
+            None => {}
+        }
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        self.end();
+    }
+}
+
+/// A new trace id combines the start time and span id, so it is unique across processes in practice
+fn new_trace_id(start_unix_time: SystemTime, span_id: u64) -> u128 {
+    ((unix_nanos(start_unix_time) as u64 as u128) << 64) | span_id as u128
+}
+
+fn unix_nanos(time: SystemTime) -> i64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as i64
+}
+
+/// The location of the caller, with its file registered so it can be resolved at analysis time
+#[track_caller]
+fn caller_location() -> SourceLocation {
+    let location = std::panic::Location::caller();
+    SourceLocation {
+        file: registry::register_label(location.file()),
+        line: location.line(),
+    }
+}
+
+/// Whether the `disabled` feature compiles all instrumentation away
+const DISABLED: bool = cfg!(feature = "disabled");
+
+/// Datapoint counting the records dropped by [ChronographBuilder::with_max_records]
+const RECORDS_OVERFLOWED: &str = "records_overflowed";
+
+/// Datapoints recorded for spans with a deadline, see [Span::set_deadline]
+const DEADLINE_EXCEEDED: &str = "deadline_exceeded";
+const DEADLINE_OVERRUN: &str = "deadline_overrun";
+
+/// Datapoint of the time a span's thread spent waiting for a CPU, see [ChronographBuilder::with_run_delay]
+const RUN_DELAY: &str = "run_delay";
+
+/// A phase inside a [Span], started by [Span::enter_segment] and ended when dropped
+#[derive(Debug)]
+pub struct SpanSegment<'a> {
+    span: &'a mut Span,
+    datapoint_id: DatapointId,
+}
+
+impl Deref for SpanSegment<'_> {
+    type Target = Span;
+    fn deref(&self) -> &Self::Target {
+        self.span
+    }
+}
+
+impl DerefMut for SpanSegment<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.span
+    }
+}
+
+impl Drop for SpanSegment<'_> {
+    fn drop(&mut self) {
+        self.span.record_instant(self.datapoint_id);
+    }
+}
+
+/// A [Span] that multiple threads can record into, started by [Span::into_handle].
+///
+/// Clones share the same span, which is recorded when the last handle is dropped.
+#[derive(Debug, Clone)]
+pub struct SpanHandle(Arc<Mutex<Span>>);
+
+impl SpanHandle {
+    /// Lock the span to record into it, blocking while another thread holds it
+    pub fn lock(&self) -> MutexGuard<'_, Span> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl From<Span> for SpanHandle {
+    fn from(value: Span) -> Self {
+        Self(Arc::new(Mutex::new(value)))
+    }
+}
+
+type DropCallback = Box<dyn FnOnce(&mut Span) + Send>;
+
+type EndCallback = Box<dyn FnOnce(&SpanData) + Send>;
+
+/// Callbacks registered with [Span::on_drop] and [Span::on_end], which belong only to the original span and are not cloned
+#[derive(Default)]
+struct DropCallbacks {
+    on_drop: Vec<DropCallback>,
+    on_end: Vec<EndCallback>,
+}
+
+impl Clone for DropCallbacks {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl Debug for DropCallbacks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.on_drop.len() + self.on_end.len())
     }
 }
 
 struct ChronographContext {
     recorder: SpanRecorder,
     processors: Vec<SpanProcessor>,
+    encoders: Vec<SpanEncoder>,
     sample_rate: SampleRate,
+    next_id: AtomicU64,
+    clock: Clock,
+    register_names: bool,
+    name_rate_limiter: Option<NameRateLimiter>,
+    record_capacity: usize,
+    max_records: usize,
+    packed_instants: bool,
+    /// In nanoseconds, see [ChronographBuilder::with_min_record_duration]
+    min_record_duration: u64,
+    staging: Option<Staging>,
+    source_locations: bool,
+    thread_info: bool,
+    run_delay: bool,
+    /// See [ChronographBuilder::with_span_dump]
+    span_dump: bool,
+    watchdog: Option<Watchdog>,
+    error_backtraces: bool,
+    error_hook: Option<ErrorHook>,
+    default_datapoints: DefaultDatapoints,
+    /// See [ChronographBuilder::with_partition_datapoint]
+    partition_datapoint: Option<DatapointId>,
+    #[cfg(feature = "introspect")]
+    recent_spans: Option<introspect::RecentSpans>,
+}
+
+impl ChronographContext {
+    /// The id of a span name, registering it when [ChronographBuilder::with_name_registry] is enabled
+    fn name_id(&self, name: &str) -> DatapointId {
+        match self.register_names {
+            true => registry::register_name(name),
+            false => DatapointId::from(name),
+        }
+    }
+
+    /// Record a finished span, staging it when [ChronographBuilder::with_staging] is enabled
+    fn record_span(&self, span_data: SpanData) {
+        match &self.staging {
+            Some(staging) => staging.stage(span_data),
+            None => self.process_span(span_data),
+        }
+    }
+
+    /// Whether an ended span lasted long enough to be recorded, see [ChronographBuilder::with_min_record_duration]
+    fn meets_min_duration(&self, span_data: &SpanData) -> bool {
+        span_data
+            .end_instant
+            .saturating_sub(span_data.start_instant)
+            >= self.min_record_duration
+    }
+
+    /// Pass a finished span through the processors to the recorder
+    fn process_span(&self, mut span_data: SpanData) {
+        if span_data.partition_key.is_none() {
+            span_data.partition_key = self.partition_key(&span_data);
+        }
+        for encoder in self.encoders.iter() {
+            encoder.encode(&mut span_data);
+        }
+        for post_processor in self.processors.iter() {
+            post_processor.post_process_span(&span_data);
+        }
+        #[cfg(feature = "introspect")]
+        if let Some(recent_spans) = &self.recent_spans {
+            recent_spans.push(&span_data);
+        }
+        self.recorder.record_span(span_data);
+    }
+
+    /// The partition key of a span from its designated datapoint, see [ChronographBuilder::with_partition_datapoint]
+    fn partition_key(&self, span_data: &SpanData) -> Option<u64> {
+        let datapoint_id = self.partition_datapoint?;
+        span_data
+            .attributes
+            .iter()
+            .find(|x| x.key == datapoint_id)
+            .map(|x| &x.value)
+            .or_else(|| {
+                span_data
+                    .records
+                    .iter()
+                    .find(|x| x.datapoint_id == datapoint_id)
+                    .map(|x| &x.value)
+            })
+            .and_then(RecordValue::partition_key)
+    }
+
+    /// Describes how spans from this chronograph are measured
+    fn batch_metadata(&self) -> BatchMetadata {
+        BatchMetadata {
+            clock_source: self.clock.source(),
+            clock_resolution_nanos: self.clock.resolution().as_nanos() as u64,
+            sampling: SamplingConfig {
+                sampler: self.sample_rate.name().to_owned(),
+                rate: self.sample_rate.rate().max(1),
+                name_rate_limit: self.name_rate_limiter.as_ref().map(|x| NameRateLimit {
+                    spans_per_second: x.spans_per_second,
+                    burst: x.burst,
+                }),
+            },
+        }
+    }
 }
 
 impl Debug for ChronographContext {
@@ -362,7 +1913,21 @@ impl Debug for ChronographContext {
         f.debug_struct("ChronographContext")
             .field("recorder", &self.recorder)
             .field("sample_rate", &self.sample_rate)
+            .field("clock_source", &self.clock.source())
+            .field("name_rate_limiter", &self.name_rate_limiter.is_some())
+            .field("staging", &self.staging.is_some())
             .field("processors_count", &self.processors.len())
+            .field("encoders_count", &self.encoders.len())
+            .field("source_locations", &self.source_locations)
+            .field("thread_info", &self.thread_info)
+            .field("run_delay", &self.run_delay)
+            .field("packed_instants", &self.packed_instants)
+            .field("span_dump", &self.span_dump)
+            .field("watchdog", &self.watchdog.as_ref().map(|x| x.timeout()))
+            .field("error_backtraces", &self.error_backtraces)
+            .field("error_hook", &self.error_hook.is_some())
+            .field("default_datapoints", &self.default_datapoints.len())
+            .field("partition_datapoint", &self.partition_datapoint)
             .finish()
     }
 }
@@ -387,11 +1952,30 @@ impl From<u64> for SampleRate {
 }
 
 impl SampleRate {
+    /// The name of the sampler, as reported in [SamplingConfig::sampler]
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::All => "all",
+            Self::Pow2(_) => "pow2",
+            Self::Modulo(_) => "modulo",
+        }
+    }
+
+    /// The configured rate, where 0 means every span is sampled
+    pub fn rate(&self) -> u64 {
+        match self {
+            Self::All => 0,
+            Self::Pow2(x) | Self::Modulo(x) => *x,
+        }
+    }
+
     pub fn sample(&self, span_id: u64) -> bool {
+        // span ids start at 1, so the first span of a process is still the first one sampled
+        let index = span_id.wrapping_sub(1);
         match self {
             Self::All => true,
-            Self::Pow2(x) => span_id & (x - 1) == 0,
-            Self::Modulo(x) => span_id % x == 0,
+            Self::Pow2(x) => index & (x - 1) == 0,
+            Self::Modulo(x) => index % x == 0,
         }
     }
 }
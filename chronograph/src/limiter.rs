@@ -0,0 +1,52 @@
+//! Per-name rate limiting of sampled spans, see [crate::ChronographBuilder::with_name_rate_limit].
+
+use std::time::Instant;
+
+use crate::schema::DatapointId;
+
+/// A token bucket for every span name, so one noisy name can't consume the whole sampling budget
+#[derive(Debug)]
+pub(crate) struct NameRateLimiter {
+    pub(crate) spans_per_second: u64,
+    pub(crate) burst: u64,
+    buckets: scc::HashMap<u64, TokenBucket>,
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl NameRateLimiter {
+    pub(crate) fn new(spans_per_second: u64, burst: u64) -> Self {
+        Self {
+            spans_per_second,
+            burst: burst.max(1),
+            buckets: scc::HashMap::default(),
+        }
+    }
+
+    /// Take a token from the bucket of the given name, returning false when it is empty
+    pub(crate) fn try_acquire(&self, name: DatapointId) -> bool {
+        let now = Instant::now();
+        let mut bucket = self
+            .buckets
+            .entry(name.value)
+            .or_insert_with(|| TokenBucket {
+                tokens: self.burst as f64,
+                last_refill: now,
+            });
+        let bucket = bucket.get_mut();
+        let refill =
+            now.duration_since(bucket.last_refill).as_secs_f64() * self.spans_per_second as f64;
+        bucket.tokens = (bucket.tokens + refill).min(self.burst as f64);
+        bucket.last_refill = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
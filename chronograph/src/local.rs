@@ -4,58 +4,400 @@
 //!
 //! # Example
 //! ```rust
-//! use chronograph::{end_threadlocal_span, get_threadlocal_span, start_threadlocal_span};
+//! use chronograph::{end_threadlocal_span, start_threadlocal_span, with_threadlocal_span};
 //!
 //! start_threadlocal_span();
-//! get_threadlocal_span().record_instant("my_op_start");
-//! get_threadlocal_span().record_value("count", 42);
-//! get_threadlocal_span().record_instant("my_op_end");
+//! with_threadlocal_span(|span| {
+//!     span.record_instant("my_op_start");
+//!     span.record_value("count", 42);
+//!     span.record_instant("my_op_end");
+//! });
 //! end_threadlocal_span();
 //! ```
+//!
+//! Thread-local spans form a stack, so nested instrumentation doesn't clobber the spans around it.
+//! [start_threadlocal_child_span] pushes a child of the current span, which [end_threadlocal_span] ends,
+//! making its parent current again:
+//! ```rust
+//! use chronograph::{end_threadlocal_span, start_threadlocal_child_span, start_threadlocal_span, with_threadlocal_span};
+//!
+//! start_threadlocal_span();
+//! let parent_id = with_threadlocal_span(|span| span.span_id());
+//! start_threadlocal_child_span();
+//! assert_eq!(with_threadlocal_span(|span| span.parent_span_id()), Some(parent_id));
+//! end_threadlocal_span();
+//! assert_eq!(with_threadlocal_span(|span| span.span_id()), parent_id);
+//! end_threadlocal_span();
+//! ```
+//!
+//! [enter_threadlocal_span] pushes a named child span until its guard is dropped.
+//!
+//! With [crate::ChronographBuilder::with_span_dump], the thread-local spans of every thread can be inspected
+//! from any thread with [dump_current_spans], such as from a debug endpoint or a deadlock detector.
 
-use crate::Span;
-use std::cell::RefCell;
+use crate::{
+    global,
+    schema::{DatapointId, SpanData},
+    thread, Span,
+};
+use std::{
+    cell::{Cell, RefCell},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, Weak,
+    },
+};
+
+/// The latest snapshot of a thread's thread-local span, readable from other threads
+type DumpSlot = Arc<Mutex<Option<SpanData>>>;
+
+/// The dump slot of every thread that has published a span
+static DUMP_SLOTS: Mutex<Vec<Weak<Mutex<Option<SpanData>>>>> = Mutex::new(Vec::new());
+
+/// Incremented by every [dump_current_spans], asking threads to publish the spans they have accessed since
+static DUMP_GENERATION: AtomicU64 = AtomicU64::new(0);
 
 thread_local! {
-    static CURRENT_SPAN: RefCell<Option<Span>> = RefCell::new(None);
+    /// The stack of thread-local spans, the last being the current span and the others its parents.
+    /// Spans are boxed so references from [get_threadlocal_span] stay valid when the stack grows.
+    #[allow(clippy::vec_box)]
+    static SPAN_STACK: RefCell<Vec<Box<Span>>> = const { RefCell::new(Vec::new()) };
+    static DUMP_SLOT: RefCell<Option<DumpSlot>> = const { RefCell::new(None) };
+    /// Whether the current span may have been recorded into since it was last published
+    static DUMP_DIRTY: Cell<bool> = const { Cell::new(false) };
+    /// The [DUMP_GENERATION] when this thread last published its span
+    static DUMP_PUBLISHED: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Snapshot every live thread-local span across threads without ending them, see [crate::ChronographBuilder::with_span_dump].
+///
+/// Spans are published when their thread starts, sets, takes or ends a thread-local span,
+/// and the first time it accesses its span after a dump, so a dump reflects the spans of other threads as of then,
+/// and the span of the calling thread as of now. Each snapshot carries the [crate::schema::ThreadInfo] of its thread.
+pub fn dump_current_spans() -> Vec<SpanData> {
+    DUMP_GENERATION.fetch_add(1, Ordering::Relaxed);
+    if DUMP_DIRTY.try_with(Cell::get).unwrap_or_default() {
+        SPAN_STACK
+            .try_with(|s| {
+                if let Ok(stack) = s.try_borrow() {
+                    publish(stack.last().map(|x| &**x));
+                }
+            })
+            .ok();
+    }
+    let mut slots = DUMP_SLOTS.lock().unwrap_or_else(|e| e.into_inner());
+    // forget slots of threads that have exited
+    slots.retain(|x| x.strong_count() > 0);
+    slots
+        .iter()
+        .filter_map(Weak::upgrade)
+        .filter_map(|x| x.lock().unwrap_or_else(|e| e.into_inner()).clone())
+        .collect()
+}
+
+/// Publish a snapshot of this thread's span for [dump_current_spans], or clear it when there is no span
+fn publish(span: Option<&Span>) {
+    DUMP_DIRTY.try_with(|x| x.set(false)).ok();
+    DUMP_PUBLISHED
+        .try_with(|x| x.set(DUMP_GENERATION.load(Ordering::Relaxed)))
+        .ok();
+    DUMP_SLOT
+        .try_with(|slot| {
+            let mut slot = slot.borrow_mut();
+            let snapshot = match span {
+                Some(span) if span.context.span_dump => {
+                    let mut snapshot = span.snapshot();
+                    snapshot.thread.get_or_insert_with(thread::current_thread);
+                    Some(snapshot)
+                }
+                // only clear a slot that was ever published to, so threads without a dump never register one
+                _ if slot.is_none() => return,
+                _ => None,
+            };
+            let slot = slot.get_or_insert_with(|| {
+                let slot = DumpSlot::default();
+                DUMP_SLOTS
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .push(Arc::downgrade(&slot));
+                slot
+            });
+            *slot.lock().unwrap_or_else(|e| e.into_inner()) = snapshot;
+        })
+        .ok();
 }
 
 /// Start a new current thread-local span from the global chronograph.
+#[track_caller]
 pub fn start_threadlocal_span() {
-    set_threadlocal_span(super::global().start_span());
+    set_threadlocal_span(global::start_global_span());
 }
 
-/// Set the current thread-local span.
+/// Set the current thread-local span, ending the span it replaces, if any.
 pub fn set_threadlocal_span(span: Span) {
-    CURRENT_SPAN.with(|s| {
-        *s.borrow_mut() = Some(span);
+    publish(Some(&span));
+    let replaced = SPAN_STACK.with(|s| {
+        let mut stack = s.borrow_mut();
+        let replaced = stack.pop();
+        stack.push(Box::new(span));
+        replaced
     });
+    // ended outside of the borrow, so its callbacks can use the thread-local span
+    drop(replaced);
+}
+
+/// Push a span as the current thread-local span, keeping the previous current span as its parent
+/// until the pushed span is taken or ended.
+pub fn push_threadlocal_span(span: Span) {
+    publish(Some(&span));
+    SPAN_STACK.with(|s| s.borrow_mut().push(Box::new(span)));
+}
+
+/// Run `f` with the current thread-local span.
+/// If no span exists, a new one will be automatically created using the global chronograph.
+///
+/// The span is taken off the thread-local stack while `f` runs, so nothing `f` calls can end it from under it.
+/// Thread-local spans started, taken or ended within `f` therefore apply to its parent,
+/// and the span returns to its place on the stack afterwards, even if `f` panics.
+#[track_caller]
+pub fn with_threadlocal_span<R>(f: impl FnOnce(&mut Span) -> R) -> R {
+    /// Returns the span to its place on the stack, even if `f` panics
+    struct Restore {
+        span: Option<Box<Span>>,
+        depth: usize,
+    }
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            if let Some(span) = self.span.take() {
+                // when the thread is exiting, the span ends here instead
+                SPAN_STACK
+                    .try_with(|s| {
+                        let mut stack = s.borrow_mut();
+                        let depth = self.depth.min(stack.len());
+                        stack.insert(depth, span);
+                    })
+                    .ok();
+            }
+        }
+    }
+    let (span, depth) = SPAN_STACK.with(|s| {
+        let mut stack = s.borrow_mut();
+        let span = stack.pop();
+        (span, stack.len())
+    });
+    let span = match span {
+        Some(span) => span,
+        None => {
+            let span = Box::new(global::start_global_span());
+            publish(Some(&span));
+            span
+        }
+    };
+    // the span is recorded into after this, so it is only published once a dump asks for it
+    if DUMP_DIRTY.get() && DUMP_PUBLISHED.get() != DUMP_GENERATION.load(Ordering::Relaxed) {
+        publish(Some(&span));
+    }
+    DUMP_DIRTY.set(span.context.span_dump);
+    let mut restore = Restore {
+        span: Some(span),
+        depth,
+    };
+    f(restore.span.as_deref_mut().unwrap())
 }
 
 /// Get a mutable reference to the current thread-local span.
 /// If no span exists, a new one will be automatically created using the global chronograph.
 /// This ensures that a valid span is always available.
-pub fn get_threadlocal_span() -> &'static mut Span {
-    CURRENT_SPAN.with(|s| {
-        let mut span_ref = s.borrow_mut();
-        if span_ref.is_none() {
-            *span_ref = Some(super::global().start_span());
+///
+/// Prefer [with_threadlocal_span], which can't outlive the span.
+///
+/// # Safety
+///
+/// The reference must not be used after the span is taken or ended, such as by [end_threadlocal_span],
+/// [take_threadlocal_span], [set_threadlocal_span] or a guard of the [crate::macros], including from code that runs while it is held.
+/// Starting other thread-local spans while it is held is fine.
+pub unsafe fn get_threadlocal_span() -> &'static mut Span {
+    SPAN_STACK.with(|s| {
+        let mut stack = s.borrow_mut();
+        if stack.is_empty() {
+            stack.push(Box::new(global::start_global_span()));
+            publish(stack.last().map(|x| &**x));
         }
-        // Safety: we just ensured the Option is Some, and we need a static lifetime
-        // to return a reference from a thread local. This is safe because the thread local
-        // storage ensures the data lives for the thread's lifetime.
-        unsafe { std::mem::transmute(span_ref.as_mut().unwrap()) }
+        let span = stack.last().unwrap();
+        // the caller records after this returns, so the span is only published once a dump asks for it
+        if DUMP_DIRTY.get() && DUMP_PUBLISHED.get() != DUMP_GENERATION.load(Ordering::Relaxed) {
+            publish(Some(span));
+        }
+        DUMP_DIRTY.set(span.context.span_dump);
+        // Safety: the span is boxed, so its address stays stable when spans are pushed onto the stack,
+        // and the caller guarantees it is not used after the span is popped off the stack.
+        let span: *mut Span = &mut **stack.last_mut().unwrap();
+        unsafe { &mut *span }
     })
 }
 
-/// Take the current thread-local span, leaving `None` in its place.
+/// Whether there is a current thread-local span and it is sampled, without starting one when there is none
+pub fn is_threadlocal_span_sampled() -> bool {
+    SPAN_STACK.with(|s| s.borrow().last().is_some_and(|x| x.is_sampled()))
+}
+
+/// Take the current thread-local span, leaving its parent, if any, as the current thread-local span.
 ///
-/// This is useful to pass a span to pass to a new thread, where you can call [set_span] to set it.
+/// This is useful to pass a span to pass to a new thread, where you can call [set_threadlocal_span] or [crate::macros::set_span] to set it.
 pub fn take_threadlocal_span() -> Option<Span> {
-    CURRENT_SPAN.with(|s| s.borrow_mut().take())
+    SPAN_STACK.with(|s| {
+        let mut stack = s.borrow_mut();
+        let span = stack.pop();
+        publish(stack.last().map(|x| &**x));
+        span.map(|x| *x)
+    })
 }
 
 /// Explicitly end the current thread-local span, dropping it from memory if it existed.
+///
+/// Its parent, if any, becomes the current thread-local span again.
 pub fn end_threadlocal_span() {
-    CURRENT_SPAN.with(|s| s.borrow_mut().take());
+    drop(take_threadlocal_span());
+}
+
+/// Start a child of the current thread-local span and push it as the current thread-local span,
+/// until it is ended with [end_threadlocal_span] and its parent is current again, so nested instrumentation composes.
+///
+/// Without a current thread-local span, a new span is started from the global chronograph.
+#[track_caller]
+pub fn start_threadlocal_child_span() {
+    push_threadlocal_span(new_threadlocal_child_span());
+}
+
+/// Start a child of the current thread-local span, or a new span from the global chronograph when there is none,
+/// without pushing it as the current thread-local span, such as to instrument a future with [crate::future::SpanFutureExt::in_span].
+#[track_caller]
+pub fn new_threadlocal_child_span() -> Span {
+    let parent = take_threadlocal_span();
+    let span = match &parent {
+        Some(parent) => parent.child_span(),
+        None => global::start_global_span(),
+    };
+    if let Some(parent) = parent {
+        push_threadlocal_span(parent);
+    }
+    span
+}
+
+/// Start a span named `name` as the current thread-local span until the returned guard is dropped.
+///
+/// The span is a child of the current thread-local span, or a new span from the global chronograph when there is none.
+/// Dropping the guard ends the span and restores the previous thread-local span, which backs [crate::macros::instrument].
+#[track_caller]
+pub fn enter_threadlocal_span(name: &str) -> ThreadLocalSpanGuard {
+    let mut span = new_threadlocal_child_span();
+    span.set_name(name);
+    ThreadLocalSpanGuard::enter(span)
+}
+
+/// Push spans onto the thread-local stack, the last becoming the current span, returning the depth they start at
+pub(crate) fn enter_threadlocal_spans(spans: Vec<Span>) -> usize {
+    SPAN_STACK.with(|s| {
+        let mut stack = s.borrow_mut();
+        let depth = stack.len();
+        stack.extend(spans.into_iter().map(Box::new));
+        publish(stack.last().map(|x| &**x));
+        depth
+    })
+}
+
+/// Remove the spans from `depth` up from the thread-local stack, which were pushed by [enter_threadlocal_spans]
+pub(crate) fn exit_threadlocal_spans(depth: usize) -> Vec<Span> {
+    SPAN_STACK.with(|s| {
+        let mut stack = s.borrow_mut();
+        let depth = depth.min(stack.len());
+        let spans = stack.split_off(depth);
+        publish(stack.last().map(|x| &**x));
+        spans.into_iter().map(|x| *x).collect()
+    })
+}
+
+/// End spans removed from the thread-local stack, children before their parents
+pub(crate) fn end_threadlocal_spans(mut spans: Vec<Span>) {
+    while spans.pop().is_some() {}
+}
+
+/// Record an instant in the current thread-local span, and again when the returned guard is dropped.
+///
+/// Like [Span::enter_segment], the segment is a pair of instants with the same datapoint id,
+/// while the thread-local span remains free to record into. This backs [crate::macros::time_block].
+#[track_caller]
+pub fn enter_threadlocal_segment(datapoint_id: impl Into<DatapointId>) -> ThreadLocalSegment {
+    let datapoint_id = datapoint_id.into();
+    with_threadlocal_span(|span| {
+        span.record_instant(datapoint_id);
+    });
+    ThreadLocalSegment { datapoint_id }
+}
+
+/// Record an instant in the current thread-local span when the returned guard is dropped,
+/// such as to measure how long cleanup takes when a scope exits. This backs [crate::macros::defer_record].
+pub fn defer_threadlocal_instant(datapoint_id: impl Into<DatapointId>) -> ThreadLocalSegment {
+    ThreadLocalSegment {
+        datapoint_id: datapoint_id.into(),
+    }
+}
+
+/// Records an instant in the current thread-local span when dropped,
+/// ending a segment from [enter_threadlocal_segment] or marking an instant deferred with [defer_threadlocal_instant]
+#[must_use = "the segment ends as soon as the guard is dropped"]
+pub struct ThreadLocalSegment {
+    datapoint_id: DatapointId,
+}
+
+impl Drop for ThreadLocalSegment {
+    fn drop(&mut self) {
+        // a span that was taken or ended within the segment has nothing to record into
+        SPAN_STACK
+            .try_with(|s| {
+                if let Some(span) = s.borrow_mut().last_mut() {
+                    span.record_instant(self.datapoint_id);
+                    DUMP_DIRTY.with(|x| x.set(span.context.span_dump));
+                }
+            })
+            .ok();
+    }
+}
+
+/// Keeps a span as the current thread-local span, see [enter_threadlocal_span]
+#[must_use = "the span ends as soon as the guard is dropped"]
+pub struct ThreadLocalSpanGuard {
+    depth: usize,
+    exit: Option<DatapointId>,
+}
+
+impl ThreadLocalSpanGuard {
+    /// Push `span` as the current thread-local span until the guard is dropped, which backs [crate::macros::span]
+    pub fn enter(span: Span) -> Self {
+        Self {
+            depth: enter_threadlocal_spans(vec![span]),
+            exit: None,
+        }
+    }
+
+    /// Record an instant datapoint in the span right before it ends, including when unwinding from a panic
+    pub fn with_exit_instant(mut self, datapoint_id: impl Into<DatapointId>) -> Self {
+        self.exit = Some(datapoint_id.into());
+        self
+    }
+}
+
+impl Drop for ThreadLocalSpanGuard {
+    fn drop(&mut self) {
+        let mut spans = exit_threadlocal_spans(self.depth);
+        // children left open within the guard end first
+        let span = match spans.is_empty() {
+            true => None,
+            false => Some(spans.remove(0)),
+        };
+        end_threadlocal_spans(spans);
+        if let (Some(mut span), Some(exit)) = (span, self.exit) {
+            span.record_instant(exit);
+        }
+    }
 }
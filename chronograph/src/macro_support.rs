@@ -0,0 +1,48 @@
+//! Support for code generated by the [crate::macros], which is not part of the stable API.
+
+use std::fmt::Display;
+
+use crate::{
+    local::with_threadlocal_span,
+    schema::{RecordValue, SpanStatus},
+};
+
+/// A function argument captured by `#[instrument(args(...))]`.
+///
+/// Its value is converted with its `Into<RecordValue>` impl when it has one, and with its `Display` impl otherwise.
+/// The choice is made at compile time by method resolution, which prefers [IntoArgValue] on the value
+/// over [DisplayArgValue] on a reference to it.
+pub struct ArgValue<'a, T: ?Sized>(pub &'a T);
+
+pub trait IntoArgValue {
+    fn record_value(&self) -> RecordValue;
+}
+
+impl<T: Clone + Into<RecordValue>> IntoArgValue for ArgValue<'_, T> {
+    fn record_value(&self) -> RecordValue {
+        self.0.clone().into()
+    }
+}
+
+pub trait DisplayArgValue {
+    fn record_value(&self) -> RecordValue;
+}
+
+impl<T: Display + ?Sized> DisplayArgValue for &ArgValue<'_, T> {
+    fn record_value(&self) -> RecordValue {
+        RecordValue::Utf8String(self.0.to_string())
+    }
+}
+
+/// Mark the current thread-local span as failed when the result is an error, for `#[instrument(err)]`.
+///
+/// This is a function rather than generated code so the error type does not need to be known to call methods on it.
+pub fn record_result<T, E: Display>(result: Result<T, E>) -> Result<T, E> {
+    if let Err(error) = &result {
+        with_threadlocal_span(|span| {
+            let message = span.is_sampled().then(|| error.to_string());
+            span.set_status(SpanStatus::Error(message));
+        });
+    }
+    result
+}
@@ -0,0 +1,81 @@
+//! Process-wide metadata, recorded once at startup when enabled by [crate::ChronographBuilder::with_process_start_span].
+
+use std::{thread::available_parallelism, time::Duration};
+
+use crate::{schema::RecordValue, Chronograph};
+
+/// The build of the application, passed to [crate::ChronographBuilder::with_process_start_span]
+/// so it is captured in the application's crate rather than in chronograph's
+#[derive(Debug)]
+pub(crate) struct BuildInfo {
+    pub(crate) version: String,
+    pub(crate) git_hash: Option<String>,
+}
+
+/// Record the "process_start" span, bypassing sampling so it is always present
+pub(crate) fn record_process_start_span(chronograph: &Chronograph, build_info: BuildInfo) {
+    let context = &chronograph.context;
+    let mut span = chronograph.start_named_span("process_start");
+    span.force_sample();
+    span.record_unix_time("process_start")
+        .record_value("pid", std::process::id())
+        .record_value("version", RecordValue::Utf8String(build_info.version))
+        .record_value(
+            "chronograph_version",
+            RecordValue::Utf8String(env!("CARGO_PKG_VERSION").to_owned()),
+        )
+        .record_value(
+            "os",
+            RecordValue::Utf8String(std::env::consts::OS.to_owned()),
+        )
+        .record_value(
+            "arch",
+            RecordValue::Utf8String(std::env::consts::ARCH.to_owned()),
+        )
+        .record_value(
+            "cpu_count",
+            available_parallelism().map(|x| x.get()).unwrap_or(0),
+        )
+        .record_value(
+            "sampler",
+            RecordValue::Utf8String(context.sample_rate.name().to_owned()),
+        )
+        .record_value("sample_rate", context.sample_rate.rate())
+        .record_value(
+            "clock_source",
+            RecordValue::Utf8String(format!("{:?}", context.clock.source())),
+        )
+        .record_value("processors_count", context.processors.len())
+        .record_value("record_capacity", context.record_capacity)
+        .record_value("packed_instants", context.packed_instants)
+        .record_value("source_locations", context.source_locations)
+        .record_value("thread_info", context.thread_info)
+        // "run_delay" is the datapoint of the measured delay itself
+        .record_value("run_delay_enabled", context.run_delay);
+    if let Some(git_hash) = build_info.git_hash {
+        span.record_value("git_hash", RecordValue::Utf8String(git_hash));
+    }
+    if let Some(staging) = &context.staging {
+        span.record_value("staging_chunk_size", staging.chunk_size());
+    }
+    if let Some(watchdog) = &context.watchdog {
+        span.record_duration("watchdog_timeout", watchdog.timeout());
+    }
+    if context.max_records != usize::MAX {
+        span.record_value("max_records", context.max_records);
+    }
+    if context.min_record_duration > 0 {
+        span.record_duration(
+            "min_record_duration",
+            Duration::from_nanos(context.min_record_duration),
+        );
+    }
+    if let Some(limiter) = &context.name_rate_limiter {
+        span.record_value("name_rate_limit", limiter.spans_per_second)
+            .record_value("name_rate_burst", limiter.burst);
+    }
+    // ended without the minimum duration check, which the span is too short to pass
+    if let Some(span_data) = span.finish() {
+        context.record_span(span_data);
+    }
+}
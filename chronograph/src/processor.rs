@@ -1,6 +1,22 @@
 //! Traits for user to hook into completed spans by reference.
+//!
+//! Processors run in the order they were added, before the span is handed to the recorder.
+//! An [EncodeSpan] added with [crate::ChronographBuilder::with_encoder] serializes selected spans into a user-defined format
+//! and attaches the bytes as a record before any processor runs, for downstream systems that expect a pre-encoded event embedded in the span.
+//!
+//! # Example
+//! ```rust
+//! use chronograph::schema::SpanData;
+//! use chronograph::Chronograph;
+//!
+//! let chronograph = Chronograph::builder()
+//!     .with_encoder("audit_event", |span: &SpanData| {
+//!         span.parent_span_id.is_none().then(|| format!("{{\"span_id\":{}}}", span.span_id).into_bytes())
+//!     })
+//!     .build();
+//! ```
 
-use crate::schema::SpanData;
+use crate::schema::{DatapointId, RecordData, RecordValue, SpanData};
 
 pub enum SpanProcessor {
     Dyn(Box<dyn ProcessSpan>),
@@ -17,3 +33,39 @@ impl SpanProcessor {
 pub trait ProcessSpan: Send + Sync {
     fn process_span(&self, span: &SpanData);
 }
+
+/// Serializes a span into a user-defined format, as soon as it ends and before it is batched
+pub trait EncodeSpan: Send + Sync {
+    /// Encode the span, or return None to skip it
+    fn encode_span(&self, span: &SpanData) -> Option<Vec<u8>>;
+}
+
+impl<F> EncodeSpan for F
+where
+    F: Fn(&SpanData) -> Option<Vec<u8>> + Send + Sync,
+{
+    fn encode_span(&self, span: &SpanData) -> Option<Vec<u8>> {
+        self(span)
+    }
+}
+
+/// An [EncodeSpan] attaching its output with a datapoint id, see [crate::ChronographBuilder::with_encoder]
+pub(crate) struct SpanEncoder {
+    pub(crate) datapoint_id: DatapointId,
+    pub(crate) encoder: Box<dyn EncodeSpan>,
+}
+
+impl SpanEncoder {
+    /// Attach the encoded span as a [RecordValue::Bytes] record, unless the encoder skips it
+    pub(crate) fn encode(&self, span_data: &mut SpanData) {
+        if let Some(bytes) = self.encoder.encode_span(span_data) {
+            span_data.records.push(RecordData {
+                datapoint_id: self.datapoint_id,
+                value: RecordValue::Bytes(bytes),
+                unit: None,
+                location: None,
+                instant: None,
+            });
+        }
+    }
+}
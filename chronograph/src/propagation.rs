@@ -0,0 +1,121 @@
+//! Propagation of spans across services with the W3C Trace Context `traceparent` header.
+//!
+//! A service injects the header with [crate::Span::traceparent] when calling another service,
+//! which extracts it with [crate::Chronograph::start_span_from_traceparent] to start a span in the same trace,
+//! recorded as a child of the caller's span.
+//!
+//! # Example
+//! ```rust
+//! use chronograph::Chronograph;
+//!
+//! let client = Chronograph::builder().build();
+//! let server = Chronograph::builder().build();
+//!
+//! let request = client.start_span();
+//! let header = request.traceparent().to_string();
+//!
+//! let handler = server.start_span_from_traceparent(&header).unwrap();
+//! assert_eq!(handler.trace_id(), request.trace_id());
+//! assert_eq!(handler.parent_span_id(), Some(request.span_id()));
+//! ```
+
+use std::{fmt::Display, str::FromStr};
+
+/// The name of the header carrying a [TraceParent]
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+
+/// The sampled flag of [TraceParent::flags]
+const SAMPLED_FLAG: u8 = 0x01;
+
+/// A parsed `traceparent` header, identifying the remote span a request was sent from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceParent {
+    pub trace_id: u128,
+    /// The id of the span that sent the request
+    pub span_id: u64,
+    pub flags: u8,
+}
+
+impl TraceParent {
+    /// Whether the remote span was sampled, in which case the span started from it is always sampled too
+    pub fn is_sampled(&self) -> bool {
+        self.flags & SAMPLED_FLAG != 0
+    }
+}
+
+impl Display for TraceParent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "00-{:032x}-{:016x}-{:02x}",
+            self.trace_id, self.span_id, self.flags
+        )
+    }
+}
+
+impl FromStr for TraceParent {
+    type Err = TraceParentError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = s.trim().split('-');
+        let version = parse_hex_field(fields.next(), 2)? as u8;
+        if version == 0xff {
+            return Err(TraceParentError::UnsupportedVersion(version));
+        }
+        let trace_id = parse_hex_field(fields.next(), 32)?;
+        let span_id = parse_hex_field(fields.next(), 16)? as u64;
+        let flags = parse_hex_field(fields.next(), 2)? as u8;
+        // later versions may append fields, which are ignored
+        if version == 0 && fields.next().is_some() {
+            return Err(TraceParentError::Malformed);
+        }
+        if trace_id == 0 || span_id == 0 {
+            return Err(TraceParentError::InvalidId);
+        }
+        Ok(Self {
+            trace_id,
+            span_id,
+            flags,
+        })
+    }
+}
+
+/// A field of exactly `len` lowercase hex digits
+fn parse_hex_field(field: Option<&str>, len: usize) -> Result<u128, TraceParentError> {
+    match field {
+        Some(field)
+            if field.len() == len
+                && field
+                    .bytes()
+                    .all(|x| x.is_ascii_digit() || (b'a'..=b'f').contains(&x)) =>
+        {
+            u128::from_str_radix(field, 16).map_err(|_| TraceParentError::Malformed)
+        }
+        _ => Err(TraceParentError::Malformed),
+    }
+}
+
+/// Returned when a `traceparent` header can't be parsed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceParentError {
+    /// The header doesn't have the `version-trace_id-span_id-flags` format
+    Malformed,
+    /// The version is the invalid version `ff`
+    UnsupportedVersion(u8),
+    /// The trace id or the span id is all zeroes
+    InvalidId,
+}
+
+impl Display for TraceParentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Malformed => write!(f, "malformed traceparent header"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported traceparent version {version:02x}")
+            }
+            Self::InvalidId => write!(f, "traceparent trace id or span id is zero"),
+        }
+    }
+}
+
+impl std::error::Error for TraceParentError {}
@@ -0,0 +1,202 @@
+//! Pre-aggregation of span durations, implementing the "metrics always, traces sampled" pattern.
+//!
+//! # Example
+//! ```rust
+//! use chronograph::recorder::aggregate::AggregatingRecorder;
+//! use chronograph::recorder::SpanRecorder;
+//! use chronograph::Chronograph;
+//!
+//! // forward the raw spans of 1 in 16 traces to the inner recorder, while aggregating every span
+//! let recorder = AggregatingRecorder::new(SpanRecorder::NoOp(), 16);
+//! let aggregator = recorder.aggregator();
+//! let chronograph = Chronograph::builder().with_recorder(recorder).build();
+//! for _ in 0..100 {
+//!     chronograph.start_named_span("checkout");
+//! }
+//! let (_, histogram) = &aggregator.snapshot()[0];
+//! assert_eq!(histogram.count(), 100);
+//! ```
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    recorder::{RecordSpan, ShutdownReport, SpanRecorder},
+    schema::{BatchMetadata, DatapointId, SpanData},
+    SampleRate,
+};
+
+/// A histogram of nanosecond durations with power-of-two buckets
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Histogram {
+    /// `buckets[i]` counts values in `[2^(i-1), 2^i)`, with `buckets[0]` counting zero
+    buckets: [u64; 65],
+    count: u64,
+    sum: u128,
+    min: u64,
+    max: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            buckets: [0; 65],
+            count: 0,
+            sum: 0,
+            min: u64::MAX,
+            max: 0,
+        }
+    }
+}
+
+impl Histogram {
+    pub fn record(&mut self, value: u64) {
+        self.buckets[(u64::BITS - value.leading_zeros()) as usize] += 1;
+        self.count += 1;
+        self.sum += value as u128;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn sum(&self) -> u128 {
+        self.sum
+    }
+
+    pub fn min(&self) -> Option<u64> {
+        (self.count > 0).then_some(self.min)
+    }
+
+    pub fn max(&self) -> Option<u64> {
+        (self.count > 0).then_some(self.max)
+    }
+
+    pub fn mean(&self) -> Option<u64> {
+        (self.count > 0).then(|| (self.sum / self.count as u128) as u64)
+    }
+
+    /// Approximate the value at the given quantile in `0.0..=1.0`, as the upper bound of its bucket
+    pub fn quantile(&self, quantile: f64) -> Option<u64> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = ((quantile.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (i, bucket) in self.buckets.iter().enumerate() {
+            seen += bucket;
+            if seen >= rank {
+                let upper_bound = match i {
+                    0 => 0,
+                    64 => u64::MAX,
+                    i => (1u64 << i) - 1,
+                };
+                return Some(upper_bound.clamp(self.min, self.max));
+            }
+        }
+        Some(self.max)
+    }
+
+    /// Add all values recorded by another histogram
+    pub fn merge(&mut self, other: &Histogram) {
+        for (bucket, other) in self.buckets.iter_mut().zip(other.buckets.iter()) {
+            *bucket += other;
+        }
+        self.count += other.count;
+        self.sum += other.sum;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+}
+
+/// Aggregates span durations into a [Histogram] per span name
+#[derive(Debug, Default)]
+pub struct HistogramAggregator {
+    histograms: Mutex<HashMap<Option<DatapointId>, Histogram>>,
+}
+
+impl HistogramAggregator {
+    pub fn record(&self, span: &SpanData) {
+        let mut histograms = self.histograms.lock().unwrap_or_else(|e| e.into_inner());
+        histograms
+            .entry(span.name)
+            .or_default()
+            .record(span.end_instant.saturating_sub(span.start_instant));
+    }
+
+    /// Copy the current histograms, keyed by span name
+    pub fn snapshot(&self) -> Vec<(Option<DatapointId>, Histogram)> {
+        let histograms = self.histograms.lock().unwrap_or_else(|e| e.into_inner());
+        histograms.iter().map(|(k, v)| (*k, v.clone())).collect()
+    }
+
+    /// Take the current histograms, resetting the aggregator for the next reporting interval
+    pub fn take(&self) -> Vec<(Option<DatapointId>, Histogram)> {
+        let mut histograms = self.histograms.lock().unwrap_or_else(|e| e.into_inner());
+        histograms.drain().collect()
+    }
+}
+
+/// A recorder that feeds every span to a [HistogramAggregator], and only forwards a sample of raw spans to an inner recorder.
+///
+/// The [crate::Chronograph] should sample every span, which is the default, so the histograms see all spans.
+/// Raw spans are sampled by trace, so the spans of a trace are forwarded or dropped together,
+/// and the [BatchMetadata] passed to the inner recorder reports the raw sample rate so counts can be extrapolated.
+pub struct AggregatingRecorder {
+    aggregator: Arc<HistogramAggregator>,
+    inner: SpanRecorder,
+    raw_sample_rate: SampleRate,
+}
+
+impl AggregatingRecorder {
+    /// Forward the spans of one in every `raw_sample_rate` traces to `inner`. As with [crate::ChronographBuilder::with_sample_rate],
+    /// it is most efficient to use a power of two, and 0 forwards every span.
+    pub fn new(inner: impl Into<SpanRecorder>, raw_sample_rate: u64) -> Self {
+        Self {
+            aggregator: Arc::new(HistogramAggregator::default()),
+            inner: inner.into(),
+            raw_sample_rate: SampleRate::from(raw_sample_rate),
+        }
+    }
+
+    /// The aggregator receiving every span, to be read periodically by the application
+    pub fn aggregator(&self) -> Arc<HistogramAggregator> {
+        Arc::clone(&self.aggregator)
+    }
+}
+
+impl RecordSpan for AggregatingRecorder {
+    fn record_span(&self, span: SpanData) {
+        self.aggregator.record(&span);
+        // the low half of a trace id is its root span id for traces started by a chronograph
+        if self.raw_sample_rate.sample(span.trace_id as u64) {
+            self.inner.record_span(span);
+        }
+    }
+
+    fn set_batch_metadata(&self, mut metadata: BatchMetadata) {
+        // raw spans are a sample of the spans the chronograph sampled, so both rates apply to them
+        if self.raw_sample_rate.rate() > 1 {
+            let sampling = &mut metadata.sampling;
+            if sampling.rate <= 1 {
+                sampling.sampler = self.raw_sample_rate.name().to_owned();
+            }
+            sampling.rate = sampling.rate.saturating_mul(self.raw_sample_rate.rate());
+        }
+        self.inner.set_batch_metadata(metadata);
+    }
+
+    fn shutdown(&self) -> ShutdownReport {
+        self.inner.shutdown()
+    }
+}
+
+impl From<AggregatingRecorder> for SpanRecorder {
+    fn from(value: AggregatingRecorder) -> Self {
+        Self::Dyn(Box::new(value))
+    }
+}
@@ -1,21 +1,25 @@
 use std::{
-    sync::{
-        mpsc::{self, Receiver, Sender},
-        Arc,
-    },
-    time::{Duration, SystemTime},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
 
-use scc::Queue;
-
-use crate::schema::{SpanBatch, SpanData};
+use crate::{
+    recorder::{
+        health::HealthReporter,
+        pipeline::{BatchPipeline, PipelineCollector},
+        queue::QueueBackend,
+        ShutdownReport,
+    },
+    registry,
+    schema::{BatchMetadata, DictionaryEntry, SpanBatch, SpanData},
+};
 
-/// A [super::SpanRecorder] that batches spans and sends them to a collector running in a separate thread
+/// A [super::SpanRecorder] that batches spans and sends them to a collector running in a separate thread,
+/// using a [BatchPipeline] of [SpanData]
 #[derive(Debug)]
 pub struct BatchingSpanRecorder {
-    batch: Arc<Queue<SpanData>>,
-    batch_size_threshold: usize,
-    thread_tx: Sender<ThreadAction>,
+    pipeline: BatchPipeline<SpanData>,
+    metadata: Arc<Mutex<BatchMetadata>>,
 }
 
 impl BatchingSpanRecorder {
@@ -23,29 +27,38 @@ impl BatchingSpanRecorder {
         collector: Box<dyn BatchCollector + Send>,
         options: BatchCollectionOptions,
     ) -> Self {
-        let batch = Arc::new(Queue::default());
-        let (thread_tx, thread_rx) = mpsc::channel();
-        CollectThread {
+        let metadata = Arc::new(Mutex::new(BatchMetadata::default()));
+        let collector = SpanBatchCollector {
             collector,
-            thread_rx,
-            batch_size_threshold: options.batch_size_threshold,
-            batch_time_threshold: options.batch_time_threshold,
-            next_collect_time: SystemTime::now() + options.batch_time_threshold,
-            batch: Arc::clone(&batch),
-        }
-        .spawn();
+            metadata: Arc::clone(&metadata),
+            dictionary_interval: options.dictionary_interval,
+            next_dictionary_time: None,
+        };
         Self {
-            batch,
-            batch_size_threshold: options.batch_size_threshold,
-            thread_tx: thread_tx,
+            pipeline: BatchPipeline::start(Box::new(collector), options),
+            metadata,
         }
     }
 
     pub fn record_span(&self, span: SpanData) {
-        self.batch.push(span);
-        if self.batch.len() == self.batch_size_threshold {
-            self.thread_tx.send(ThreadAction::Wake).ok();
-        }
+        self.pipeline.push(span);
+    }
+
+    /// Set the [BatchMetadata] attached to every exported batch
+    pub fn set_batch_metadata(&self, metadata: BatchMetadata) {
+        *self.metadata.lock().unwrap_or_else(|e| e.into_inner()) = metadata;
+    }
+
+    /// Current statistics of the recorder and its collector thread
+    pub fn stats(&self) -> RecorderStats {
+        self.pipeline.stats()
+    }
+
+    /// Flush all pending spans to the collector and stop the collector thread, blocking until it has finished.
+    ///
+    /// Spans recorded after shutdown are dropped and counted in [ShutdownReport::spans_dropped].
+    pub fn shutdown(&self) -> ShutdownReport {
+        self.pipeline.shutdown()
     }
 }
 
@@ -60,21 +73,48 @@ impl<F: FnMut(SpanBatch)> BatchCollector for F {
     }
 }
 
-pub struct BatchCollectionOptions {
-    batch_size_threshold: usize,
-    batch_time_threshold: Duration,
+/// A point-in-time view of a [BatchingSpanRecorder]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecorderStats {
+    /// The effective batch size threshold, which changes over time when adaptive batch sizing is enabled
+    pub batch_size_threshold: usize,
+    /// Spans currently waiting to be collected
+    pub queue_len: usize,
+    pub spans_dropped: u64,
+    pub batches_exported: u64,
+    pub exporter_errors: u64,
+    /// How long the most recent call to [BatchCollector::collect] took
+    pub last_export_latency: Duration,
+}
+
+/// Options of a [BatchingSpanRecorder], or of a [BatchPipeline] of any item `T`
+pub struct BatchCollectionOptions<T = SpanData> {
+    pub(super) batch_size_threshold: usize,
+    pub(super) batch_time_threshold: Duration,
+    pub(super) target_export_latency: Option<Duration>,
+    pub(super) min_batch_size: usize,
+    pub(super) max_batch_size: usize,
+    pub(super) queue_backend: QueueBackend<T>,
+    pub(super) dictionary_interval: Option<Duration>,
+    pub(super) health_reporter: Option<HealthReporter>,
 }
 
-impl Default for BatchCollectionOptions {
+impl<T> Default for BatchCollectionOptions<T> {
     fn default() -> Self {
         Self {
             batch_size_threshold: 4096,
             batch_time_threshold: Duration::from_secs(60),
+            target_export_latency: None,
+            min_batch_size: 1,
+            max_batch_size: 65536,
+            queue_backend: QueueBackend::Unbounded,
+            dictionary_interval: None,
+            health_reporter: None,
         }
     }
 }
 
-impl BatchCollectionOptions {
+impl<T> BatchCollectionOptions<T> {
     pub fn with_batch_size_threshold(mut self, batch_size_threshold: usize) -> Self {
         self.batch_size_threshold = batch_size_threshold;
         self
@@ -84,57 +124,87 @@ impl BatchCollectionOptions {
         self.batch_time_threshold = batch_time_threshold;
         self
     }
-}
 
-/// A thread that collects spans from a [BatchingSpanRecorder] and sends them to a [BatchCollector]
-struct CollectThread {
-    collector: Box<dyn BatchCollector + Send>,
-    thread_rx: Receiver<ThreadAction>,
-    batch_size_threshold: usize,
-    batch_time_threshold: Duration,
-    next_collect_time: SystemTime,
-    batch: Arc<Queue<SpanData>>,
+    /// Enable adaptive batch sizing, where the batch size threshold grows or shrinks after every export
+    /// to keep the time spent in [BatchCollector::collect] near the given target.
+    ///
+    /// The batch size threshold starts at the configured value, bounded by [Self::with_batch_size_bounds].
+    pub fn with_target_export_latency(mut self, target_export_latency: Duration) -> Self {
+        self.target_export_latency = Some(target_export_latency);
+        self
+    }
+
+    /// Select the queue that hands spans to the collector thread, defaults to [QueueBackend::Unbounded]
+    pub fn with_queue_backend(mut self, queue_backend: QueueBackend<T>) -> Self {
+        self.queue_backend = queue_backend;
+        self
+    }
+
+    /// Report the health of the collector through the given reporter, see [crate::recorder::health].
+    ///
+    /// A panicking collector is reported as degraded, and a successful export as connected,
+    /// unless the collector reported its own health while exporting.
+    pub fn with_health_reporter(mut self, health_reporter: HealthReporter) -> Self {
+        self.health_reporter = Some(health_reporter);
+        self
+    }
+
+    /// Bounds of the batch size threshold when adaptive batch sizing is enabled, defaults to 1..=65536
+    pub fn with_batch_size_bounds(mut self, min_batch_size: usize, max_batch_size: usize) -> Self {
+        self.min_batch_size = min_batch_size;
+        self.max_batch_size = max_batch_size;
+        self
+    }
 }
 
-impl CollectThread {
-    pub fn spawn(mut self) {
-        std::thread::Builder::new()
-            .name("chronograph batch collector".to_owned())
-            .spawn(move || self.run())
-            .expect("could not spawn std thread");
-    }
-
-    pub fn run(&mut self) {
-        loop {
-            match self.thread_rx.recv_timeout(self.batch_time_threshold) {
-                Ok(ThreadAction::Shutdown) => return,
-                Ok(ThreadAction::Wake) | Err(_) => {}
-            }
-            if self.batch.len() >= self.batch_size_threshold
-                || SystemTime::now() >= self.next_collect_time
-            {
-                let mut batch: Vec<SpanData> = Vec::new();
-                while let Some(record) = self.batch.pop() {
-                    batch.push(SpanData::clone(&record));
-                }
-                if !batch.is_empty() {
-                    self.collector.collect(SpanBatch { spans: batch });
-                }
-                self.next_collect_time = SystemTime::now() + self.batch_time_threshold;
-            }
-        }
+impl BatchCollectionOptions<SpanData> {
+    /// Include the names of the [crate::registry] in the first batch and then at most once per interval,
+    /// so consumers joining a stream late can always resolve ids to names. Disabled by default.
+    pub fn with_dictionary_interval(mut self, dictionary_interval: Duration) -> Self {
+        self.dictionary_interval = Some(dictionary_interval);
+        self
     }
 }
 
-impl Drop for BatchingSpanRecorder {
-    fn drop(&mut self) {
-        // shutdown the daemon thread when the batching span recorder is dropped
-        self.thread_tx.send(ThreadAction::Shutdown).ok();
+/// Builds a [SpanBatch] from every batch of spans, passing it to the user's [BatchCollector]
+struct SpanBatchCollector {
+    collector: Box<dyn BatchCollector + Send>,
+    metadata: Arc<Mutex<BatchMetadata>>,
+    dictionary_interval: Option<Duration>,
+    /// When the dictionary is next due, or `None` before the first batch
+    next_dictionary_time: Option<Instant>,
+}
+
+impl PipelineCollector<SpanData> for SpanBatchCollector {
+    fn collect(&mut self, spans: Vec<SpanData>) {
+        let dictionary = self.take_dictionary();
+        let metadata = self
+            .metadata
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        self.collector.collect(SpanBatch {
+            spans,
+            metadata,
+            dictionary,
+        })
     }
 }
 
-#[derive(Debug, Clone)]
-enum ThreadAction {
-    Wake,
-    Shutdown,
+impl SpanBatchCollector {
+    /// The registered names, when a dictionary is due on the next batch
+    fn take_dictionary(&mut self) -> Vec<DictionaryEntry> {
+        let Some(dictionary_interval) = self.dictionary_interval else {
+            return Vec::new();
+        };
+        let now = Instant::now();
+        if self.next_dictionary_time.is_some_and(|x| now < x) {
+            return Vec::new();
+        }
+        self.next_dictionary_time = Some(now + dictionary_interval);
+        registry::registered_names()
+            .into_iter()
+            .map(|(id, name)| DictionaryEntry { id, name })
+            .collect()
+    }
 }
@@ -0,0 +1,101 @@
+//! Failure injection for testing how an application handles a misbehaving telemetry pipeline.
+//!
+//! # Example
+//! ```rust
+//! use std::time::Duration;
+//! use chronograph::recorder::batch::{BatchCollectionOptions, BatchingSpanRecorder};
+//! use chronograph::recorder::chaos::ChaosCollector;
+//! use chronograph::schema::SpanBatch;
+//!
+//! let collector = ChaosCollector::new(|batch: SpanBatch| println!("exported {} spans", batch.spans.len()))
+//!     .with_drop_probability(0.1)
+//!     .with_error_probability(0.05)
+//!     .with_delay(0.2, Duration::from_millis(50));
+//! let recorder = BatchingSpanRecorder::start(Box::new(collector), BatchCollectionOptions::default());
+//! ```
+
+use std::time::{Duration, SystemTime};
+
+use crate::{recorder::batch::BatchCollector, schema::SpanBatch};
+
+/// A [BatchCollector] that randomly delays, drops, or fails batches before passing them to an inner collector.
+///
+/// Failures are injected as panics, which the [super::batch::BatchingSpanRecorder] reports as exporter errors,
+/// while dropped batches are lost silently, like a network that swallows requests.
+#[derive(Debug)]
+pub struct ChaosCollector<C> {
+    inner: C,
+    drop_probability: f64,
+    error_probability: f64,
+    delay_probability: f64,
+    delay: Duration,
+    rng: u64,
+}
+
+impl<C: BatchCollector> ChaosCollector<C> {
+    /// Wrap a collector, injecting no failures until probabilities are configured
+    pub fn new(inner: C) -> Self {
+        let seed = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64;
+        Self {
+            inner,
+            drop_probability: 0.0,
+            error_probability: 0.0,
+            delay_probability: 0.0,
+            delay: Duration::ZERO,
+            rng: seed,
+        }
+    }
+
+    /// Probability that a batch is dropped without reaching the inner collector
+    pub fn with_drop_probability(mut self, probability: f64) -> Self {
+        self.drop_probability = probability;
+        self
+    }
+
+    /// Probability that exporting a batch fails with a panic
+    pub fn with_error_probability(mut self, probability: f64) -> Self {
+        self.error_probability = probability;
+        self
+    }
+
+    /// Probability that a batch is held for `delay` before being exported
+    pub fn with_delay(mut self, probability: f64, delay: Duration) -> Self {
+        self.delay_probability = probability;
+        self.delay = delay;
+        self
+    }
+
+    /// Seed the random number generator, so injected failures are reproducible
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = seed;
+        self
+    }
+
+    /// A uniformly distributed number in `[0, 1)`, using splitmix64
+    fn next_f64(&mut self) -> f64 {
+        self.rng = self.rng.wrapping_add(0x9e3779b97f4a7c15);
+        let mut x = self.rng;
+        x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+        x ^= x >> 31;
+        (x >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+impl<C: BatchCollector> BatchCollector for ChaosCollector<C> {
+    fn collect(&mut self, batch: SpanBatch) {
+        if self.next_f64() < self.delay_probability {
+            std::thread::sleep(self.delay);
+        }
+        if self.next_f64() < self.error_probability {
+            panic!("chronograph chaos: injected collector error");
+        }
+        if self.next_f64() < self.drop_probability {
+            return;
+        }
+        self.inner.collect(batch);
+    }
+}
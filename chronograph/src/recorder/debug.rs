@@ -0,0 +1,211 @@
+//! Human-readable span output for local debugging.
+//!
+//! [DebugPrintRecorder] prints every span as soon as it ends, with names resolved through the [crate::registry]
+//! (falling back to `#<id>` for unregistered datapoints),
+//! instants shown relative to the start of their span, and optional ANSI colors.
+//! It is meant to give immediate feedback while instrumenting code, before any real exporter is wired up.
+//!
+//! # Example
+//! ```rust
+//! use chronograph::recorder::debug::DebugPrintRecorder;
+//! use chronograph::{registry, Chronograph};
+//!
+//! registry::register_name("parsed");
+//! registry::register_name("count");
+//! let chronograph = Chronograph::builder()
+//!     .with_name_registry()
+//!     .with_recorder(DebugPrintRecorder::stderr().with_rate_limit(100))
+//!     .build();
+//! let mut span = chronograph.start_named_span("handle_request");
+//! span.record_instant("parsed");
+//! span.record_value("count", 42);
+//! ```
+//!
+//! Which prints something like:
+//! ```text
+//! handle_request span_id=1 trace_id=00000000000000000000000000000001 duration=12.3µs ok
+//!   +8.1µs       (+8.1µs)  parsed
+//!   count = 42
+//! ```
+
+use std::{
+    io::{IsTerminal, Write as _},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    fmt::{SpanFormatter, DIM, RESET},
+    recorder::{RecordSpan, ShutdownReport, SpanRecorder},
+    schema::SpanData,
+};
+
+/// Where a [DebugPrintRecorder] writes spans
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugOutput {
+    Stdout,
+    Stderr,
+}
+
+/// Prints spans in a human-readable format as they are recorded
+#[derive(Debug)]
+pub struct DebugPrintRecorder {
+    output: DebugOutput,
+    color: bool,
+    rate_limit: Option<u64>,
+    window: Mutex<RateWindow>,
+}
+
+/// Spans printed and suppressed in the current one-second window
+#[derive(Debug)]
+struct RateWindow {
+    start: Instant,
+    printed: u64,
+    suppressed: u64,
+    total_suppressed: u64,
+}
+
+impl DebugPrintRecorder {
+    /// Print spans to the given output, using colors when it is a terminal
+    pub fn new(output: DebugOutput) -> Self {
+        let color = match output {
+            DebugOutput::Stdout => std::io::stdout().is_terminal(),
+            DebugOutput::Stderr => std::io::stderr().is_terminal(),
+        };
+        Self {
+            output,
+            color,
+            rate_limit: None,
+            window: Mutex::new(RateWindow {
+                start: Instant::now(),
+                printed: 0,
+                suppressed: 0,
+                total_suppressed: 0,
+            }),
+        }
+    }
+
+    pub fn stdout() -> Self {
+        Self::new(DebugOutput::Stdout)
+    }
+
+    pub fn stderr() -> Self {
+        Self::new(DebugOutput::Stderr)
+    }
+
+    /// Force ANSI colors on or off, instead of detecting whether the output is a terminal
+    pub fn with_color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Print at most `spans_per_second` spans each second, summarizing how many were suppressed
+    /// when the next span arrives after the second ends, or on shutdown
+    pub fn with_rate_limit(mut self, spans_per_second: u64) -> Self {
+        self.rate_limit = Some(spans_per_second);
+        self
+    }
+
+    /// Format a span the way it would be printed
+    pub fn format_span(&self, span: &SpanData) -> String {
+        SpanFormatter {
+            color: self.color,
+            dictionary: &[],
+        }
+        .format_span(span)
+    }
+
+    /// Whether the span may be printed, and how many spans were suppressed in the window that just closed
+    fn admit(&self) -> (bool, u64) {
+        let Some(rate_limit) = self.rate_limit else {
+            return (true, 0);
+        };
+        let mut window = self.window.lock().unwrap_or_else(|e| e.into_inner());
+        let mut suppressed = 0;
+        if window.start.elapsed() >= Duration::from_secs(1) {
+            suppressed = window.suppressed;
+            window.start = Instant::now();
+            window.printed = 0;
+            window.suppressed = 0;
+        }
+        if window.printed < rate_limit {
+            window.printed += 1;
+            (true, suppressed)
+        } else {
+            window.suppressed += 1;
+            window.total_suppressed += 1;
+            (false, suppressed)
+        }
+    }
+
+    /// Print how many spans were suppressed in the current window, so the count is not lost when no span follows it
+    fn flush_suppressed(&self) {
+        let suppressed = {
+            let mut window = self.window.lock().unwrap_or_else(|e| e.into_inner());
+            std::mem::take(&mut window.suppressed)
+        };
+        if suppressed > 0 {
+            self.write(&self.suppressed_summary(suppressed));
+        }
+    }
+
+    fn suppressed_summary(&self, suppressed: u64) -> String {
+        format!(
+            "{}... {suppressed} spans suppressed by rate limit{}\n",
+            self.style(DIM),
+            self.style(RESET)
+        )
+    }
+
+    fn style(&self, code: &'static str) -> &'static str {
+        match self.color {
+            true => code,
+            false => "",
+        }
+    }
+
+    fn write(&self, text: &str) {
+        match self.output {
+            DebugOutput::Stdout => std::io::stdout().lock().write_all(text.as_bytes()),
+            DebugOutput::Stderr => std::io::stderr().lock().write_all(text.as_bytes()),
+        }
+        .ok();
+    }
+}
+
+impl RecordSpan for DebugPrintRecorder {
+    fn record_span(&self, span: SpanData) {
+        let (admitted, suppressed) = self.admit();
+        let mut text = String::new();
+        if suppressed > 0 {
+            text.push_str(&self.suppressed_summary(suppressed));
+        }
+        if admitted {
+            text.push_str(&self.format_span(&span));
+        }
+        if !text.is_empty() {
+            self.write(&text);
+        }
+    }
+
+    fn shutdown(&self) -> ShutdownReport {
+        self.flush_suppressed();
+        let window = self.window.lock().unwrap_or_else(|e| e.into_inner());
+        ShutdownReport {
+            spans_dropped: window.total_suppressed,
+            ..ShutdownReport::default()
+        }
+    }
+}
+
+impl Drop for DebugPrintRecorder {
+    fn drop(&mut self) {
+        self.flush_suppressed();
+    }
+}
+
+impl From<DebugPrintRecorder> for SpanRecorder {
+    fn from(value: DebugPrintRecorder) -> Self {
+        Self::Dyn(Box::new(value))
+    }
+}
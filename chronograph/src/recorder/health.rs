@@ -0,0 +1,129 @@
+//! Health of the connection between a recorder and the system it exports to.
+//!
+//! A network collector reports whether it is connected through a [HealthReporter], so applications can surface
+//! the status of their telemetry pipeline in their own health checks instead of discovering silent data loss later.
+//! When given to [super::batch::BatchCollectionOptions::with_health_reporter], a panicking collector is reported
+//! as degraded, and a successful export as connected unless the collector reported its own health while exporting.
+//!
+//! # Example
+//! ```rust
+//! use std::sync::{Arc, Mutex};
+//! use chronograph::recorder::health::{ExporterHealth, HealthReporter};
+//!
+//! let status = Arc::new(Mutex::new(None));
+//! let status_clone = Arc::clone(&status);
+//! let reporter = HealthReporter::new(move |health: &ExporterHealth| {
+//!     *status_clone.lock().unwrap() = Some(health.clone());
+//! });
+//! // a collector holds a clone of the reporter and reports as its connection changes
+//! reporter.disconnected("connection refused");
+//! assert_eq!(
+//!     *status.lock().unwrap(),
+//!     Some(ExporterHealth::Disconnected("connection refused".to_owned()))
+//! );
+//! reporter.connected();
+//! assert!(reporter.health().is_healthy());
+//! ```
+
+use std::{
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+/// The state of an exporter's connection
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum ExporterHealth {
+    /// Batches are being exported
+    #[default]
+    Connected,
+    /// Batches are being exported, but some are lost or delayed, with the reason
+    Degraded(String),
+    /// Batches can't be exported, with the reason
+    Disconnected(String),
+}
+
+impl ExporterHealth {
+    pub fn is_healthy(&self) -> bool {
+        matches!(self, Self::Connected)
+    }
+}
+
+/// Notified whenever the [ExporterHealth] changes
+pub type HealthCallback = Box<dyn Fn(&ExporterHealth) + Send + Sync>;
+
+/// Tracks the [ExporterHealth] of an exporter, notifying a callback whenever it changes.
+///
+/// Clones share the same state, so a collector and the application can hold one each.
+#[derive(Clone)]
+pub struct HealthReporter(Arc<HealthState>);
+
+struct HealthState {
+    callback: HealthCallback,
+    health: Mutex<ExporterHealth>,
+    /// Incremented on every report, so automatic reports can tell whether a collector reported on its own
+    reports: AtomicU64,
+}
+
+impl HealthReporter {
+    /// A reporter starting out connected, calling `callback` on every change of health
+    pub fn new(callback: impl Fn(&ExporterHealth) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(HealthState {
+            callback: Box::new(callback),
+            health: Mutex::new(ExporterHealth::Connected),
+            reports: AtomicU64::new(0),
+        }))
+    }
+
+    /// Report the current health, notifying the callback when it changed
+    pub fn report(&self, health: ExporterHealth) {
+        self.0.reports.fetch_add(1, Ordering::Relaxed);
+        let changed = {
+            let mut current = self.0.health.lock().unwrap_or_else(|e| e.into_inner());
+            (*current != health).then(|| {
+                *current = health.clone();
+                health
+            })
+        };
+        // called without the lock, so the callback can read the health or report through a clone of the reporter
+        if let Some(health) = changed {
+            (self.0.callback)(&health);
+        }
+    }
+
+    pub fn connected(&self) {
+        self.report(ExporterHealth::Connected);
+    }
+
+    pub fn degraded(&self, reason: impl Into<String>) {
+        self.report(ExporterHealth::Degraded(reason.into()));
+    }
+
+    pub fn disconnected(&self, reason: impl Into<String>) {
+        self.report(ExporterHealth::Disconnected(reason.into()));
+    }
+
+    /// The most recently reported health
+    pub fn health(&self) -> ExporterHealth {
+        self.0
+            .health
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// The number of reports so far, see [HealthState::reports]
+    pub(crate) fn report_count(&self) -> u64 {
+        self.0.reports.load(Ordering::Relaxed)
+    }
+}
+
+impl Debug for HealthReporter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HealthReporter")
+            .field("health", &self.health())
+            .finish()
+    }
+}
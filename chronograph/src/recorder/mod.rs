@@ -1,8 +1,20 @@
-use std::fmt::Debug;
+use std::{
+    fmt::Debug,
+    time::{Duration, Instant},
+};
 
-use crate::{recorder::batch::BatchingSpanRecorder, schema::SpanData};
+use crate::{
+    recorder::batch::{BatchingSpanRecorder, RecorderStats},
+    schema::{BatchMetadata, SpanData},
+};
 
+pub mod aggregate;
 pub mod batch;
+pub mod chaos;
+pub mod debug;
+pub mod health;
+pub mod pipeline;
+pub mod queue;
 
 /// Records spans, which can either be:
 /// - a [BatchingSpanRecorder]
@@ -45,6 +57,15 @@ impl From<()> for SpanRecorder {
 /// Used in [SpanRecorder::Dyn] to allow users to provide their own span recorder.
 pub trait RecordSpan: Send + Sync {
     fn record_span(&self, span: SpanData);
+
+    /// Called once when the [crate::Chronograph] is built, describing how its spans are measured.
+    /// The default implementation does nothing.
+    fn set_batch_metadata(&self, _metadata: BatchMetadata) {}
+
+    /// Flush and stop the recorder. The default implementation does nothing.
+    fn shutdown(&self) -> ShutdownReport {
+        ShutdownReport::default()
+    }
 }
 
 impl<F: Fn(SpanData) + Send + Sync> RecordSpan for F {
@@ -61,4 +82,50 @@ impl SpanRecorder {
             Self::NoOp() => {}
         }
     }
+
+    /// Pass [BatchMetadata] to the recorder, to be attached to every batch it exports
+    pub fn set_batch_metadata(&self, metadata: BatchMetadata) {
+        match self {
+            Self::Batching(x) => x.set_batch_metadata(metadata),
+            Self::Dyn(x) => x.set_batch_metadata(metadata),
+            Self::NoOp() => {}
+        }
+    }
+
+    /// Current statistics of the recorder, when it is a [BatchingSpanRecorder]
+    pub fn stats(&self) -> Option<RecorderStats> {
+        match self {
+            Self::Batching(x) => Some(x.stats()),
+            Self::Dyn(_) | Self::NoOp() => None,
+        }
+    }
+
+    /// Flush and stop the recorder, reporting how cleanly it terminated
+    pub fn shutdown(&self) -> ShutdownReport {
+        let start = Instant::now();
+        let report = match self {
+            Self::Batching(x) => x.shutdown(),
+            Self::Dyn(x) => x.shutdown(),
+            Self::NoOp() => ShutdownReport::default(),
+        };
+        ShutdownReport {
+            elapsed: start.elapsed(),
+            ..report
+        }
+    }
+}
+
+/// Returned by [SpanRecorder::shutdown] to describe how recording terminated
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShutdownReport {
+    /// Spans that were pending at shutdown and successfully flushed to the collector
+    pub spans_flushed: u64,
+    /// Spans that were lost, either recorded after shutdown or part of a batch that failed to export
+    pub spans_dropped: u64,
+    /// Batches successfully exported over the lifetime of the recorder
+    pub batches_exported: u64,
+    /// Time taken to shutdown
+    pub elapsed: Duration,
+    /// Number of times the collector panicked while exporting a batch
+    pub exporter_errors: u64,
 }
@@ -0,0 +1,303 @@
+//! The queue and collector thread behind the [super::batch::BatchingSpanRecorder], reusable for any item type.
+//!
+//! A [BatchPipeline] queues items pushed from any thread and hands them in batches to a [PipelineCollector]
+//! on a dedicated thread, with the same size and time thresholds, adaptive batch sizing, queue backends and
+//! panic isolation as span batching. Batching [SpanData](crate::schema::SpanData) is one instantiation of it.
+//!
+//! # Example
+//! ```rust
+//! use std::sync::mpsc;
+//! use chronograph::recorder::batch::BatchCollectionOptions;
+//! use chronograph::recorder::pipeline::BatchPipeline;
+//!
+//! #[derive(Debug, Clone)]
+//! struct Event {
+//!     id: u64,
+//! }
+//!
+//! let (tx, rx) = mpsc::channel();
+//! let pipeline = BatchPipeline::start(
+//!     Box::new(move |events: Vec<Event>| tx.send(events.len()).unwrap()),
+//!     BatchCollectionOptions::default().with_batch_size_threshold(2),
+//! );
+//! pipeline.push(Event { id: 1 });
+//! pipeline.push(Event { id: 2 });
+//! assert_eq!(rx.recv().unwrap(), 2);
+//! assert_eq!(pipeline.shutdown().batches_exported, 1);
+//! ```
+
+use std::{
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::recorder::{
+    batch::{BatchCollectionOptions, RecorderStats},
+    health::HealthReporter,
+    queue::SpanQueue,
+    ShutdownReport,
+};
+
+/// Batches items of type `T` and sends them to a [PipelineCollector] running in a separate thread.
+///
+/// In the [RecorderStats] and [ShutdownReport] of a pipeline, span counts are counts of items.
+#[derive(Debug)]
+pub struct BatchPipeline<T> {
+    queue: Arc<dyn SpanQueue<T>>,
+    thread_tx: Sender<ThreadAction>,
+    counters: Arc<BatchCounters>,
+    shutdown: AtomicBool,
+}
+
+/// A trait for collecting items after they have been batched by a [BatchPipeline]
+pub trait PipelineCollector<T> {
+    fn collect(&mut self, items: Vec<T>);
+}
+
+impl<T, F: FnMut(Vec<T>)> PipelineCollector<T> for F {
+    fn collect(&mut self, items: Vec<T>) {
+        self(items)
+    }
+}
+
+impl<T: Clone + Send + Sync + 'static> BatchPipeline<T> {
+    /// Start the collector thread
+    pub fn start(
+        collector: Box<dyn PipelineCollector<T> + Send>,
+        options: BatchCollectionOptions<T>,
+    ) -> Self {
+        let queue = options.queue_backend.build();
+        let counters = Arc::new(BatchCounters {
+            batch_size_threshold: AtomicUsize::new(options.batch_size_threshold),
+            ..Default::default()
+        });
+        let (thread_tx, thread_rx) = mpsc::channel();
+        CollectThread {
+            collector,
+            thread_rx,
+            batch_time_threshold: options.batch_time_threshold,
+            adaptive: options
+                .target_export_latency
+                .map(|target_latency| AdaptiveBatchSize {
+                    target_latency,
+                    min_batch_size: options.min_batch_size.max(1),
+                    max_batch_size: options.max_batch_size.max(1),
+                }),
+            next_collect_time: SystemTime::now() + options.batch_time_threshold,
+            health_reporter: options.health_reporter,
+            queue: Arc::clone(&queue),
+            counters: Arc::clone(&counters),
+        }
+        .spawn();
+        Self {
+            queue,
+            thread_tx,
+            counters,
+            shutdown: AtomicBool::new(false),
+        }
+    }
+}
+
+impl<T> BatchPipeline<T> {
+    /// Queue an item, which is dropped when the queue is full or the pipeline has been shut down
+    pub fn push(&self, item: T) {
+        if self.shutdown.load(Ordering::Relaxed) {
+            self.counters.spans_dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        if self.queue.push(item).is_err() {
+            self.counters.spans_dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        // the threshold changes with adaptive batch sizing, so the queue may already be past it,
+        // but one wake per collection is enough
+        if self.queue.len() >= self.counters.batch_size_threshold.load(Ordering::Relaxed)
+            && !self.counters.wake_pending.swap(true, Ordering::AcqRel)
+        {
+            self.thread_tx.send(ThreadAction::Wake).ok();
+        }
+    }
+
+    /// Current statistics of the pipeline and its collector thread
+    pub fn stats(&self) -> RecorderStats {
+        RecorderStats {
+            batch_size_threshold: self.counters.batch_size_threshold.load(Ordering::Relaxed),
+            queue_len: self.queue.len(),
+            spans_dropped: self.counters.spans_dropped.load(Ordering::Relaxed),
+            batches_exported: self.counters.batches_exported.load(Ordering::Relaxed),
+            exporter_errors: self.counters.exporter_errors.load(Ordering::Relaxed),
+            last_export_latency: Duration::from_nanos(
+                self.counters.last_export_latency.load(Ordering::Relaxed),
+            ),
+        }
+    }
+
+    /// Flush all pending items to the collector and stop the collector thread, blocking until it has finished.
+    ///
+    /// Items pushed after shutdown are dropped and counted in [ShutdownReport::spans_dropped].
+    pub fn shutdown(&self) -> ShutdownReport {
+        let start = Instant::now();
+        self.shutdown.store(true, Ordering::Relaxed);
+        let (report_tx, report_rx) = mpsc::channel();
+        let spans_flushed = match self.thread_tx.send(ThreadAction::Shutdown(Some(report_tx))) {
+            Ok(()) => report_rx.recv().unwrap_or_default(),
+            Err(_) => 0,
+        };
+        ShutdownReport {
+            spans_flushed,
+            spans_dropped: self.counters.spans_dropped.load(Ordering::Relaxed)
+                + self.queue.len() as u64,
+            batches_exported: self.counters.batches_exported.load(Ordering::Relaxed),
+            elapsed: start.elapsed(),
+            exporter_errors: self.counters.exporter_errors.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl<T> Drop for BatchPipeline<T> {
+    fn drop(&mut self) {
+        // shutdown the daemon thread when the pipeline is dropped
+        self.thread_tx.send(ThreadAction::Shutdown(None)).ok();
+    }
+}
+
+/// A thread that collects items from a [BatchPipeline] and sends them to a [PipelineCollector]
+struct CollectThread<T> {
+    collector: Box<dyn PipelineCollector<T> + Send>,
+    thread_rx: Receiver<ThreadAction>,
+    batch_time_threshold: Duration,
+    adaptive: Option<AdaptiveBatchSize>,
+    next_collect_time: SystemTime,
+    health_reporter: Option<HealthReporter>,
+    queue: Arc<dyn SpanQueue<T>>,
+    counters: Arc<BatchCounters>,
+}
+
+impl<T: Send + 'static> CollectThread<T> {
+    pub fn spawn(mut self) {
+        std::thread::Builder::new()
+            .name("chronograph batch collector".to_owned())
+            .spawn(move || self.run())
+            .expect("could not spawn std thread");
+    }
+
+    pub fn run(&mut self) {
+        loop {
+            match self.thread_rx.recv_timeout(self.batch_time_threshold) {
+                Ok(ThreadAction::Shutdown(report_tx)) => {
+                    // flush whatever is left before exiting
+                    let spans_flushed = self.collect();
+                    if let Some(report_tx) = report_tx {
+                        report_tx.send(spans_flushed).ok();
+                    }
+                    return;
+                }
+                Ok(ThreadAction::Wake) | Err(_) => {}
+            }
+            // cleared before draining, so items pushed during the collection can wake the thread again
+            self.counters.wake_pending.store(false, Ordering::Release);
+            if self.queue.len() >= self.counters.batch_size_threshold.load(Ordering::Relaxed)
+                || SystemTime::now() >= self.next_collect_time
+            {
+                self.collect();
+                self.next_collect_time = SystemTime::now() + self.batch_time_threshold;
+            }
+        }
+    }
+
+    /// Drain the queue into a single batch and pass it to the collector, returning the number of items exported
+    fn collect(&mut self) -> u64 {
+        let mut batch: Vec<T> = Vec::new();
+        while let Some(item) = self.queue.pop() {
+            batch.push(item);
+        }
+        if batch.is_empty() {
+            return 0;
+        }
+        let count = batch.len() as u64;
+        let collector = &mut self.collector;
+        let report_count = self.health_reporter.as_ref().map(|x| x.report_count());
+        let start = Instant::now();
+        let result = catch_unwind(AssertUnwindSafe(|| collector.collect(batch)));
+        let latency = start.elapsed();
+        self.counters
+            .last_export_latency
+            .store(latency.as_nanos() as u64, Ordering::Relaxed);
+        if let Some(adaptive) = &self.adaptive {
+            let current = self.counters.batch_size_threshold.load(Ordering::Relaxed);
+            self.counters.batch_size_threshold.store(
+                adaptive.next_batch_size(current, count as usize, latency),
+                Ordering::Relaxed,
+            );
+        }
+        if let Some(health_reporter) = &self.health_reporter {
+            // a collector reporting its own health knows better than whether it returned
+            if Some(health_reporter.report_count()) == report_count {
+                match result {
+                    Ok(()) => health_reporter.connected(),
+                    Err(_) => health_reporter.degraded("collector panicked"),
+                }
+            }
+        }
+        match result {
+            Ok(()) => {
+                self.counters
+                    .batches_exported
+                    .fetch_add(1, Ordering::Relaxed);
+                count
+            }
+            Err(_) => {
+                // a panicking collector loses its batch, but must not take down the collector thread
+                self.counters
+                    .exporter_errors
+                    .fetch_add(1, Ordering::Relaxed);
+                self.counters
+                    .spans_dropped
+                    .fetch_add(count, Ordering::Relaxed);
+                0
+            }
+        }
+    }
+}
+
+/// Adjusts the batch size threshold to keep export latency near a target
+struct AdaptiveBatchSize {
+    target_latency: Duration,
+    min_batch_size: usize,
+    max_batch_size: usize,
+}
+
+impl AdaptiveBatchSize {
+    /// Estimate how many items fit in the target latency from the last export's per-item cost,
+    /// moving halfway toward the estimate to smooth out noisy measurements
+    fn next_batch_size(&self, current: usize, exported: usize, latency: Duration) -> usize {
+        let latency = latency.as_nanos().max(1);
+        let estimate = exported as u128 * self.target_latency.as_nanos() / latency;
+        let next = (current as u128 + estimate) / 2;
+        (next.min(usize::MAX as u128) as usize).clamp(self.min_batch_size, self.max_batch_size)
+    }
+}
+
+/// Counters shared between a [BatchPipeline] and its [CollectThread]
+#[derive(Debug, Default)]
+struct BatchCounters {
+    batch_size_threshold: AtomicUsize,
+    /// Whether a [ThreadAction::Wake] has been sent that the collector thread hasn't received yet
+    wake_pending: AtomicBool,
+    last_export_latency: AtomicU64,
+    spans_dropped: AtomicU64,
+    batches_exported: AtomicU64,
+    exporter_errors: AtomicU64,
+}
+
+#[derive(Debug, Clone)]
+enum ThreadAction {
+    Wake,
+    /// Flush and exit, optionally replying with the number of items flushed
+    Shutdown(Option<Sender<u64>>),
+}
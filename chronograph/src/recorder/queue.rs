@@ -0,0 +1,283 @@
+//! Queues used by the [super::batch::BatchingSpanRecorder] to hand spans to its collector thread.
+//!
+//! The backend is selected with [super::batch::BatchCollectionOptions::with_queue_backend].
+//! Every queue is generic over the queued item, so the same backends serve any [super::pipeline::BatchPipeline].
+
+use std::{
+    any::Any,
+    cell::{RefCell, UnsafeCell},
+    fmt::Debug,
+    mem::MaybeUninit,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use crossbeam_queue::ArrayQueue;
+use scc::Queue;
+
+use crate::schema::SpanData;
+
+/// A multi-producer queue of spans, or any other item `T`, drained by a single collector thread
+pub trait SpanQueue<T = SpanData>: Send + Sync {
+    /// Push an item, giving it back when the queue is full
+    #[allow(clippy::result_large_err)]
+    fn push(&self, item: T) -> Result<(), T>;
+
+    fn pop(&self) -> Option<T>;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Selects the [SpanQueue] used by a [super::batch::BatchingSpanRecorder]
+#[derive(Debug)]
+pub enum QueueBackend<T = SpanData> {
+    /// An unbounded lock-free [scc::Queue], which allocates per span. This is the default.
+    Unbounded,
+    /// A bounded, pre-allocated [ArrayQueue] of the given capacity. Spans are dropped when it is full.
+    Bounded(usize),
+    /// A lock-free single-producer single-consumer ring of the given capacity per producer thread,
+    /// so producers never contend with each other. Spans are dropped when a thread's ring is full.
+    PerThread(usize),
+    /// A user-provided queue
+    Dyn(Arc<dyn SpanQueue<T>>),
+}
+
+impl<T> Debug for dyn SpanQueue<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SpanQueue")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+impl<T: Clone + Send + Sync + 'static> QueueBackend<T> {
+    pub(crate) fn build(self) -> Arc<dyn SpanQueue<T>> {
+        match self {
+            Self::Unbounded => Arc::new(Queue::<T>::default()),
+            Self::Bounded(capacity) => Arc::new(ArrayQueue::<T>::new(capacity.max(1))),
+            Self::PerThread(capacity) => Arc::new(PerThreadQueue::<T>::new(capacity)),
+            Self::Dyn(x) => x,
+        }
+    }
+}
+
+impl<T: Clone + Send + Sync + 'static> SpanQueue<T> for Queue<T> {
+    fn push(&self, item: T) -> Result<(), T> {
+        Queue::push(self, item);
+        Ok(())
+    }
+
+    fn pop(&self) -> Option<T> {
+        Queue::pop(self).map(|x| T::clone(&x))
+    }
+
+    fn len(&self) -> usize {
+        Queue::len(self)
+    }
+}
+
+impl<T: Send> SpanQueue<T> for ArrayQueue<T> {
+    fn push(&self, item: T) -> Result<(), T> {
+        ArrayQueue::push(self, item)
+    }
+
+    fn pop(&self) -> Option<T> {
+        ArrayQueue::pop(self)
+    }
+
+    fn len(&self) -> usize {
+        ArrayQueue::len(self)
+    }
+}
+
+type ThreadBuffer<T> = Arc<SpscRing<T>>;
+
+static NEXT_PER_THREAD_QUEUE_ID: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// This thread's buffer for every [PerThreadQueue] it has pushed to, keyed by queue id
+    /// Type-erased, since each queue may hold a different item type
+    static THREAD_BUFFERS: RefCell<Vec<(usize, Arc<dyn Any + Send + Sync>)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// A [SpanQueue] with a bounded, lock-free single-producer single-consumer ring buffer per thread.
+///
+/// Each ring is only pushed to by its own thread and drained by the collector thread,
+/// so producers never contend with each other or take a lock. Spans are dropped when a thread's ring is full.
+///
+/// Spans pushed while a thread is exiting, such as thread-local spans ending in thread-local destructors,
+/// go through a shared ring of the same capacity instead.
+#[derive(Debug)]
+pub struct PerThreadQueue<T = SpanData> {
+    id: usize,
+    capacity: usize,
+    /// Also serializes the consumer side of every ring
+    buffers: Mutex<Vec<ThreadBuffer<T>>>,
+    /// Used once a thread's thread-local buffers are gone
+    shared: ArrayQueue<T>,
+    len: AtomicUsize,
+    next_buffer: AtomicUsize,
+}
+
+impl<T> PerThreadQueue<T> {
+    /// Create a queue with a ring of `capacity` items per producer thread
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            id: NEXT_PER_THREAD_QUEUE_ID.fetch_add(1, Ordering::Relaxed),
+            capacity: capacity.max(1),
+            buffers: Mutex::new(Vec::new()),
+            shared: ArrayQueue::new(capacity.max(1)),
+            len: AtomicUsize::new(0),
+            next_buffer: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl<T: Send + 'static> PerThreadQueue<T> {
+    /// This thread's ring, or None when the thread-local is gone because the thread is exiting
+    fn thread_buffer(&self) -> Option<ThreadBuffer<T>> {
+        THREAD_BUFFERS
+            .try_with(|buffers| {
+                let mut buffers = buffers.borrow_mut();
+                if let Some((_, buffer)) = buffers.iter().find(|(id, _)| *id == self.id) {
+                    if let Ok(buffer) = Arc::clone(buffer).downcast::<SpscRing<T>>() {
+                        return buffer;
+                    }
+                }
+                // forget buffers of queues that have since been dropped
+                buffers.retain(|(_, buffer)| Arc::strong_count(buffer) > 1);
+                let buffer = Arc::new(SpscRing::new(self.capacity));
+                buffers.push((self.id, Arc::clone(&buffer) as Arc<dyn Any + Send + Sync>));
+                self.buffers
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .push(Arc::clone(&buffer));
+                buffer
+            })
+            .ok()
+    }
+}
+
+impl<T: Send + 'static> SpanQueue<T> for PerThreadQueue<T> {
+    fn push(&self, item: T) -> Result<(), T> {
+        // counted before the item is published, so a concurrent pop can't take the count below zero
+        self.len.fetch_add(1, Ordering::Relaxed);
+        let pushed = match self.thread_buffer() {
+            // Safety: a ring is only ever pushed to from the thread that created it
+            Some(buffer) => unsafe { buffer.push(item) },
+            None => self.shared.push(item),
+        };
+        pushed.inspect_err(|_| {
+            self.len.fetch_sub(1, Ordering::Relaxed);
+        })
+    }
+
+    fn pop(&self) -> Option<T> {
+        if self.len.load(Ordering::Relaxed) == 0 {
+            return None;
+        }
+        let mut buffers = self.buffers.lock().unwrap_or_else(|e| e.into_inner());
+        // round-robin over buffers so one busy thread can't starve the others
+        let start = self.next_buffer.fetch_add(1, Ordering::Relaxed);
+        for i in 0..buffers.len() {
+            let index = (start + i) % buffers.len();
+            // Safety: popping is serialized by the buffers lock
+            if let Some(item) = unsafe { buffers[index].pop() } {
+                self.len.fetch_sub(1, Ordering::Relaxed);
+                return Some(item);
+            }
+        }
+        if let Some(item) = self.shared.pop() {
+            self.len.fetch_sub(1, Ordering::Relaxed);
+            return Some(item);
+        }
+        // forget empty buffers of threads that have exited
+        buffers.retain(|buffer| Arc::strong_count(buffer) > 1 || buffer.len() > 0);
+        None
+    }
+
+    fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+}
+
+/// A bounded lock-free ring buffer with one producer and one consumer
+struct SpscRing<T> {
+    slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    /// The position of the next item to pop, only written by the consumer
+    head: AtomicUsize,
+    /// The position of the next item to push, only written by the producer
+    tail: AtomicUsize,
+}
+
+// Safety: items are moved between threads, and each slot is only accessed by the producer before
+// it is published by `tail`, or by the consumer after that and before it is released by `head`
+unsafe impl<T: Send> Send for SpscRing<T> {}
+unsafe impl<T: Send> Sync for SpscRing<T> {}
+
+impl<T> SpscRing<T> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            slots: (0..capacity)
+                .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+                .collect(),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.tail
+            .load(Ordering::Acquire)
+            .wrapping_sub(self.head.load(Ordering::Acquire))
+    }
+
+    /// Push an item, giving it back when the ring is full
+    ///
+    /// # Safety
+    /// Must not be called concurrently with another push
+    #[allow(clippy::result_large_err)]
+    unsafe fn push(&self, item: T) -> Result<(), T> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        if tail.wrapping_sub(self.head.load(Ordering::Acquire)) == self.slots.len() {
+            return Err(item);
+        }
+        (*self.slots[tail % self.slots.len()].get()).write(item);
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        Ok(())
+    }
+
+    /// # Safety
+    /// Must not be called concurrently with another pop
+    unsafe fn pop(&self) -> Option<T> {
+        let head = self.head.load(Ordering::Relaxed);
+        if head == self.tail.load(Ordering::Acquire) {
+            return None;
+        }
+        let item = (*self.slots[head % self.slots.len()].get()).assume_init_read();
+        self.head.store(head.wrapping_add(1), Ordering::Release);
+        Some(item)
+    }
+}
+
+impl<T> Debug for SpscRing<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SpscRing")
+            .field("capacity", &self.slots.len())
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+impl<T> Drop for SpscRing<T> {
+    fn drop(&mut self) {
+        // Safety: dropping requires exclusive access
+        while unsafe { self.pop() }.is_some() {}
+    }
+}
@@ -0,0 +1,135 @@
+//! A global registry of names, for resolving hashed [DatapointId]s back to the names they were derived from.
+//!
+//! Names are only registered when explicitly requested, either through [register_name],
+//! the `register_datapoints` function declared by [crate::macros::datapoints],
+//! or by enabling [crate::ChronographBuilder::with_name_registry] to register span names.
+//!
+//! # Example
+//! ```rust
+//! use chronograph::registry::{register_name, resolve_name};
+//!
+//! let id = register_name("checkout");
+//! assert_eq!(resolve_name(id).as_deref(), Some("checkout"));
+//! ```
+//!
+//! An opt-in cardinality budget warns when too many distinct span or datapoint names are registered,
+//! such as names built from request paths, which would explode the label space of downstream metric systems.
+//! File and thread names registered for source locations and thread info don't count towards it.
+//! [crate::macros::datapoints] can also enforce a budget on the names it declares at build time.
+//! ```rust
+//! use chronograph::registry::set_cardinality_limit;
+//!
+//! set_cardinality_limit(1000, |exceeded| {
+//!     eprintln!("more than {} span names registered, latest: {}", exceeded.limit, exceeded.name)
+//! });
+//! ```
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, OnceLock, RwLock,
+};
+
+use crate::schema::DatapointId;
+
+static REGISTRY: OnceLock<scc::HashMap<u64, String>> = OnceLock::new();
+
+/// The ids of registered span and datapoint names, which count towards the cardinality limit
+static COUNTED: OnceLock<scc::HashSet<u64>> = OnceLock::new();
+
+static CARDINALITY_LIMIT: RwLock<Option<CardinalityLimit>> = RwLock::new(None);
+
+struct CardinalityLimit {
+    limit: usize,
+    on_exceeded: Arc<dyn Fn(&CardinalityExceeded) + Send + Sync>,
+    warned: AtomicBool,
+}
+
+/// Passed to the hook of [set_cardinality_limit] when the budget is first exceeded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CardinalityExceeded {
+    pub limit: usize,
+    /// The name that took the registry over its budget
+    pub name: String,
+}
+
+fn registry() -> &'static scc::HashMap<u64, String> {
+    REGISTRY.get_or_init(scc::HashMap::default)
+}
+
+fn counted() -> &'static scc::HashSet<u64> {
+    COUNTED.get_or_init(scc::HashSet::default)
+}
+
+/// Register a span or datapoint name, returning the [DatapointId] it hashes to
+pub fn register_name(name: &str) -> DatapointId {
+    let id = register_label(name);
+    if !counted().contains(&id.value) && counted().insert(id.value).is_ok() {
+        check_cardinality(name);
+    }
+    id
+}
+
+/// Register a name that is neither a span nor a datapoint name, such as a file or thread name,
+/// which doesn't count towards [set_cardinality_limit]
+pub(crate) fn register_label(name: &str) -> DatapointId {
+    let id = DatapointId::from(name);
+    if !registry().contains(&id.value) {
+        registry().insert(id.value, name.to_owned()).ok();
+    }
+    id
+}
+
+/// Call `on_exceeded` once, the first time more than `limit` distinct span or datapoint names are registered.
+///
+/// Names are still registered past the limit, so this only warns. Setting a new limit replaces the previous one,
+/// and `on_exceeded` may itself set a new limit.
+pub fn set_cardinality_limit(
+    limit: usize,
+    on_exceeded: impl Fn(&CardinalityExceeded) + Send + Sync + 'static,
+) {
+    let limit = CardinalityLimit {
+        limit,
+        on_exceeded: Arc::new(on_exceeded),
+        warned: AtomicBool::new(false),
+    };
+    *CARDINALITY_LIMIT.write().unwrap_or_else(|e| e.into_inner()) = Some(limit);
+    // names registered before the limit was set may already exceed it
+    let mut latest = None;
+    counted().scan(|id| latest = Some(*id));
+    if let Some(name) = latest.and_then(resolve_name) {
+        check_cardinality(&name);
+    }
+}
+
+fn check_cardinality(name: &str) {
+    let exceeded = {
+        let limit = CARDINALITY_LIMIT.read().unwrap_or_else(|e| e.into_inner());
+        match limit.as_ref() {
+            Some(limit)
+                if counted().len() > limit.limit && !limit.warned.swap(true, Ordering::Relaxed) =>
+            {
+                Some((limit.limit, Arc::clone(&limit.on_exceeded)))
+            }
+            _ => None,
+        }
+    };
+    // the lock is released first, so the hook can set a new limit
+    if let Some((limit, on_exceeded)) = exceeded {
+        on_exceeded(&CardinalityExceeded {
+            limit,
+            name: name.to_owned(),
+        });
+    }
+}
+
+/// Resolve a [DatapointId] to its registered name
+pub fn resolve_name(id: impl Into<DatapointId>) -> Option<String> {
+    registry().read(&id.into().value, |_, name| name.clone())
+}
+
+/// All registered names, in no particular order
+pub fn registered_names() -> Vec<(DatapointId, String)> {
+    let mut names = Vec::with_capacity(registry().len());
+    registry().scan(|id, name| names.push((DatapointId::from(*id), name.clone())));
+    names
+}
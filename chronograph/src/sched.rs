@@ -0,0 +1,38 @@
+//! Scheduling delay of the current thread, see [crate::ChronographBuilder::with_run_delay].
+
+use std::time::Duration;
+
+/// A reading of the time the current thread has spent runnable, but waiting for a CPU
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RunDelay {
+    thread_id: u64,
+    nanos: u64,
+}
+
+impl RunDelay {
+    /// Read the run delay of the current thread, or None when the platform doesn't expose it
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub(crate) fn now() -> Option<Self> {
+        // the second field is the time spent waiting on a run queue, in nanoseconds
+        let schedstat = std::fs::read_to_string("/proc/thread-self/schedstat").ok()?;
+        let nanos = schedstat.split_whitespace().nth(1)?.parse().ok()?;
+        Some(Self {
+            thread_id: crate::thread::os_thread_id(),
+            nanos,
+        })
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    pub(crate) fn now() -> Option<Self> {
+        None
+    }
+
+    /// The run delay accumulated since this reading, or None when the thread has changed since
+    pub(crate) fn elapsed(&self) -> Option<Duration> {
+        let now = Self::now()?;
+        match now.thread_id == self.thread_id {
+            true => Some(Duration::from_nanos(now.nanos.saturating_sub(self.nanos))),
+            false => None,
+        }
+    }
+}
@@ -1,28 +1,476 @@
 //! Serialization and deserialization of chronograph data, utilizing rkyv for fast serialization and deserialization.
 
-use std::{hash::Hasher, time::SystemTime};
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    hash::Hasher,
+    time::{Duration, SystemTime},
+};
 
-use rkyv::util::AlignedVec;
+use rkyv::{rancor::Source, util::AlignedVec};
 use zwohash::ZwoHasher;
 
+use crate::fmt::{format_nanos, SpanFormatter};
+
 #[derive(Debug, Clone, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
 pub struct SpanBatch {
     pub spans: Vec<SpanData>,
+    pub metadata: BatchMetadata,
+    /// Names from the [crate::registry], included periodically so late-joining consumers can resolve ids.
+    /// See [crate::recorder::batch::BatchCollectionOptions::with_dictionary_interval].
+    pub dictionary: Vec<DictionaryEntry>,
 }
 
-#[derive(Debug, Clone, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+/// A registered name and the [DatapointId] it hashes to
+#[derive(Debug, Clone, PartialEq, Eq, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+pub struct DictionaryEntry {
+    pub id: DatapointId,
+    pub name: String,
+}
+
+/// Describes how the spans of a [SpanBatch] were measured, so batches from different hosts can be compared
+#[derive(
+    Debug, Clone, Default, PartialEq, Eq, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize,
+)]
+pub struct BatchMetadata {
+    /// The monotonic clock backing every instant in the batch
+    pub clock_source: ClockSource,
+    /// The smallest observable tick of the clock, measured when the chronograph was built
+    pub clock_resolution_nanos: u64,
+    /// How root spans were sampled, so counts can be extrapolated
+    pub sampling: SamplingConfig,
+}
+
+/// The sampling configuration of the chronograph that recorded a batch, see [crate::ChronographBuilder::with_sample_rate]
+#[derive(Debug, Clone, PartialEq, Eq, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+pub struct SamplingConfig {
+    /// The sampler that picked root spans: `"all"`, `"pow2"`, or `"modulo"`
+    pub sampler: String,
+    /// One in this many root spans is sampled, which is 1 when every span is sampled
+    pub rate: u64,
+    /// The per-name limit applied on top of the rate, see [crate::ChronographBuilder::with_name_rate_limit]
+    pub name_rate_limit: Option<NameRateLimit>,
+}
+
+impl SamplingConfig {
+    /// Extrapolate a count of sampled spans to the number of spans that occurred.
+    ///
+    /// Spans dropped by the name rate limit or forced with [crate::Span::force_sample] are not accounted for.
+    pub fn extrapolate(&self, sampled_count: u64) -> u64 {
+        sampled_count.saturating_mul(self.rate)
+    }
+}
+
+impl Default for SamplingConfig {
+    fn default() -> Self {
+        Self {
+            sampler: "all".to_owned(),
+            rate: 1,
+            name_rate_limit: None,
+        }
+    }
+}
+
+/// A token bucket limit on sampled spans of each name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+pub struct NameRateLimit {
+    pub spans_per_second: u64,
+    pub burst: u64,
+}
+
+/// A monotonic clock that span instants can be read from, see [crate::ChronographBuilder::with_clock_source]
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize,
+)]
+pub enum ClockSource {
+    /// `QueryPerformanceCounter`, used by [std::time::Instant] on Windows
+    QueryPerformanceCounter,
+    /// `mach_absolute_time`, used by [std::time::Instant] on macOS and iOS. Does not advance while the system sleeps.
+    MachAbsoluteTime,
+    /// `CLOCK_MONOTONIC`, used by [std::time::Instant] on Linux and other unix platforms, and available on macOS and iOS.
+    /// Its rate is slewed by NTP.
+    ClockMonotonic,
+    /// `CLOCK_MONOTONIC_RAW`, available on Linux, Android, macOS and iOS. Not adjusted by NTP, so it measures the raw hardware rate.
+    ClockMonotonicRaw,
+    /// [std::time::Instant] on any other platform
+    Other,
+}
+
+impl ClockSource {
+    /// The clock behind [std::time::Instant] on the current platform
+    pub fn platform_default() -> Self {
+        if cfg!(windows) {
+            Self::QueryPerformanceCounter
+        } else if cfg!(target_vendor = "apple") {
+            Self::MachAbsoluteTime
+        } else if cfg!(unix) {
+            Self::ClockMonotonic
+        } else {
+            Self::Other
+        }
+    }
+
+    /// Whether this clock can be selected on the current platform.
+    ///
+    /// A supported clock that can't be read at runtime, such as in a restrictive sandbox, still falls back to the platform default.
+    pub fn is_supported(&self) -> bool {
+        match self {
+            Self::QueryPerformanceCounter => cfg!(windows),
+            Self::MachAbsoluteTime => cfg!(target_vendor = "apple"),
+            Self::ClockMonotonic => cfg!(unix),
+            Self::ClockMonotonicRaw => cfg!(any(
+                target_os = "linux",
+                target_os = "android",
+                target_vendor = "apple"
+            )),
+            Self::Other => *self == Self::platform_default(),
+        }
+    }
+}
+
+impl Default for ClockSource {
+    fn default() -> Self {
+        Self::platform_default()
+    }
+}
+
+#[derive(Debug, Clone, Default, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
 pub struct SpanData {
     pub span_id: u64,
+    /// The id of the span this span was started from, see [crate::Span::child_span]
+    pub parent_span_id: Option<u64>,
+    /// Groups all spans descending from the same root span, see [crate::Chronograph::start_span_in_trace]
+    pub trace_id: u128,
+    /// The hashed name of the span, see [crate::Chronograph::start_named_span]
+    pub name: Option<DatapointId>,
+    /// The role of the span in a distributed trace, see [crate::Chronograph::start_span_with_kind]
+    pub kind: SpanKind,
     pub start_unix_time: i64,
     pub start_instant: u64,
     pub end_instant: u64,
+    pub status: SpanStatus,
     pub records: Vec<RecordData>,
+    /// Static metadata about the span, see [crate::Span::set_attribute]
+    pub attributes: Vec<Attribute>,
+    /// Spans this span relates to outside of its parent, see [crate::Span::add_link]
+    pub links: Vec<SpanLink>,
+    /// Where the span was started, see [crate::ChronographBuilder::with_source_locations]
+    pub location: Option<SourceLocation>,
+    /// The thread that started the span, see [crate::ChronographBuilder::with_thread_info]
+    pub thread: Option<ThreadInfo>,
+    /// A resolved backtrace, see [crate::Span::capture_backtrace]
+    pub backtrace: Option<String>,
+    /// Keeps spans of the same entity in order when published to a partitioned transport,
+    /// see [crate::Span::set_partition_key]
+    pub partition_key: Option<u64>,
+    /// The standardized outcome of the operation, see [crate::Span::set_outcome]
+    pub outcome: Option<Outcome>,
+}
+
+impl SpanData {
+    /// How long the span lasted
+    pub fn duration(&self) -> Duration {
+        Duration::from_nanos(self.end_instant.saturating_sub(self.start_instant))
+    }
+
+    /// Whether the span was started without a parent
+    pub fn is_root(&self) -> bool {
+        self.parent_span_id.is_none()
+    }
+
+    pub fn is_error(&self) -> bool {
+        matches!(self.status, SpanStatus::Error(_))
+    }
+
+    /// The first record with the given datapoint id
+    pub fn find_record(&self, datapoint_id: impl Into<DatapointId>) -> Option<&RecordData> {
+        let datapoint_id = datapoint_id.into();
+        self.records.iter().find(|x| x.datapoint_id == datapoint_id)
+    }
+
+    /// The value of the first record with the given datapoint id
+    pub fn find_value(&self, datapoint_id: impl Into<DatapointId>) -> Option<&RecordValue> {
+        self.find_record(datapoint_id).map(|x| &x.value)
+    }
+
+    /// Every record with the given datapoint id, in the order they were recorded
+    pub fn records_with_id(
+        &self,
+        datapoint_id: impl Into<DatapointId>,
+    ) -> impl Iterator<Item = &RecordData> {
+        let datapoint_id = datapoint_id.into();
+        self.records
+            .iter()
+            .filter(move |x| x.datapoint_id == datapoint_id)
+    }
+
+    /// The records grouped by datapoint id, each group in the order they were recorded
+    pub fn records_by_id(&self) -> HashMap<DatapointId, Vec<&RecordData>> {
+        let mut records: HashMap<DatapointId, Vec<&RecordData>> = HashMap::new();
+        for record in self.records.iter() {
+            records.entry(record.datapoint_id).or_default().push(record);
+        }
+        records
+    }
+
+    /// The value of the attribute with the given key
+    pub fn find_attribute(&self, key: impl Into<DatapointId>) -> Option<&RecordValue> {
+        let key = key.into();
+        self.attributes
+            .iter()
+            .find(|x| x.key == key)
+            .map(|x| &x.value)
+    }
+
+    /// The instant records with their offset from the start of the span, in the order they were recorded
+    pub fn instants(&self) -> impl Iterator<Item = (DatapointId, Duration)> + '_ {
+        self.records.iter().filter_map(|x| match x.value {
+            RecordValue::Instant(instant) => Some((
+                x.datapoint_id,
+                Duration::from_nanos(instant.saturating_sub(self.start_instant)),
+            )),
+            _ => None,
+        })
+    }
+
+    /// Fold records collected independently, such as by another thread, into this span after its existing records
+    pub fn merge_from(&mut self, other_records: Vec<RecordData>) {
+        self.records.extend(other_records);
+    }
+
+    /// Combine a related span, such as a retried attempt, into this span so they are recorded as one.
+    ///
+    /// Records and links are appended, attributes are added unless this span already has one with the same key,
+    /// the outcome is kept unless this span already has one, and the span is widened to cover both time ranges.
+    /// The other span's status and ids are discarded.
+    pub fn merge(&mut self, other: SpanData) {
+        self.outcome = self.outcome.or(other.outcome);
+        if other.start_instant < self.start_instant {
+            self.start_instant = other.start_instant;
+            self.start_unix_time = other.start_unix_time;
+        }
+        self.end_instant = self.end_instant.max(other.end_instant);
+        self.merge_from(other.records);
+        for attribute in other.attributes {
+            if !self.attributes.iter().any(|x| x.key == attribute.key) {
+                self.attributes.push(attribute);
+            }
+        }
+        self.links.extend(other.links);
+    }
+
+    /// An estimate of the bytes this span takes in a [SpanBatch] with [BatchEncoding::Standard],
+    /// for byte-based batching, transport MTU decisions and record caps.
+    ///
+    /// It is exact up to alignment padding, and an upper bound for the other encodings.
+    /// See [SpanData::serialized_size_hint] for an estimate of any encoding.
+    pub fn estimated_serialized_size(&self) -> usize {
+        estimated_serialized_size(
+            &self.records,
+            &self.attributes,
+            &self.links,
+            &self.status,
+            self.backtrace.as_deref(),
+        )
+    }
+
+    /// An estimate of the bytes this span takes in a [SpanBatch] serialized with the given encoding,
+    /// so recorders can enforce byte-based batch limits without serializing twice.
+    ///
+    /// [BatchEncoding::PackedRepeats] is estimated as [BatchEncoding::Standard], which packing only shrinks.
+    pub fn serialized_size_hint(&self, encoding: BatchEncoding) -> usize {
+        let size = self.estimated_serialized_size();
+        if encoding != BatchEncoding::DeltaInstants {
+            return size;
+        }
+        // mirrors DeltaSpanData::from, moving plain instants out of the records into varint deltas
+        let mut instant_count = 0;
+        let mut deltas_len = 0;
+        let mut previous = self.start_instant;
+        for record in self.records.iter() {
+            if let (RecordValue::Instant(instant), None, None, None) =
+                (&record.value, record.unit, record.location, record.instant)
+            {
+                instant_count += 1;
+                deltas_len += varint_len(zigzag_encode(instant.wrapping_sub(previous) as i64));
+                previous = *instant;
+            }
+        }
+        size + size_of::<ArchivedDeltaSpanData>()
+            - size_of::<ArchivedSpanData>()
+            - instant_count * size_of::<ArchivedRecordData>()
+            + self.records.len().div_ceil(8)
+            + instant_count * size_of::<ArchivedDatapointId>()
+            + deltas_len
+    }
+
+    /// The partition of this span among `partitions`, for transports publishing spans of the same key in order.
+    ///
+    /// Returns None for spans without a partition key, which may be published to any partition.
+    pub fn partition(&self, partitions: u32) -> Option<u32> {
+        self.partition_key
+            .map(|x| (x % partitions.max(1) as u64) as u32)
+    }
+}
+
+/// Formats the span as a [crate::recorder::debug::DebugPrintRecorder] prints it, without colors.
+///
+/// Names are resolved through the [crate::registry], and instants are shown relative to the start of the span.
+impl Display for SpanData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let formatter = SpanFormatter {
+            color: false,
+            dictionary: &[],
+        };
+        f.write_str(formatter.format_span(self).trim_end())
+    }
+}
+
+/// Formats every span of the batch, each prefixed with its start relative to the earliest span of the batch.
+///
+/// Names missing from the [crate::registry] are resolved through the batch's dictionary.
+impl Display for SpanBatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "batch of {} spans, clock={:?} sampler={} rate=1/{}",
+            self.spans.len(),
+            self.metadata.clock_source,
+            self.metadata.sampling.sampler,
+            self.metadata.sampling.rate
+        )?;
+        let formatter = SpanFormatter {
+            color: false,
+            dictionary: &self.dictionary,
+        };
+        let first_instant = self.spans.iter().map(|x| x.start_instant).min();
+        for span in self.spans.iter() {
+            let offset = format!(
+                "+{}",
+                format_nanos(span.start_instant - first_instant.unwrap_or_default())
+            );
+            let text = formatter.format_span(span);
+            for (i, line) in text.lines().enumerate() {
+                match i {
+                    0 => write!(f, "\n{offset:<12} {line}")?,
+                    _ => write!(f, "\n{:<12} {line}", "")?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The role of a span in a distributed trace, matching the span kinds of OpenTelemetry
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+    rkyv::Archive,
+    rkyv::Serialize,
+    rkyv::Deserialize,
+)]
+pub enum SpanKind {
+    /// An operation within the process, with no remote parent or child
+    #[default]
+    Internal,
+    /// An outgoing request to a remote service
+    Client,
+    /// The handling of an incoming request from a remote client
+    Server,
+    /// The sending of a message to a broker, which is not awaited
+    Producer,
+    /// The processing of a message received from a broker
+    Consumer,
+}
+
+/// Whether the operation covered by a span succeeded, see [crate::Span::record_error]
+#[derive(
+    Debug, Clone, Default, PartialEq, Eq, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize,
+)]
+pub enum SpanStatus {
+    #[default]
+    Ok,
+    /// The operation failed, with an optional message describing the error
+    Error(Option<String>),
+}
+
+/// The standardized outcome of the operation covered by a span, see [crate::Span::set_outcome].
+///
+/// Unlike [SpanStatus], it tells whose fault a failure was, so error rates can exclude client errors and cancellations.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize,
+)]
+pub enum Outcome {
+    Success,
+    /// The operation failed because of the caller, such as an invalid request
+    ClientError,
+    /// The operation failed on its own side
+    ServerError,
+    /// The caller gave up on the operation before it finished
+    Cancelled,
+    /// The operation did not finish within its time budget
+    Timeout,
+}
+
+impl Outcome {
+    /// Whether the outcome counts toward an error rate, which is the case for server errors and timeouts
+    pub fn is_error(&self) -> bool {
+        matches!(self, Self::ServerError | Self::Timeout)
+    }
 }
 
 #[derive(Debug, Clone, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
 pub struct RecordData {
     pub datapoint_id: DatapointId,
     pub value: RecordValue,
+    /// The unit of a numeric value, see [crate::Span::record_value_with_unit]
+    pub unit: Option<Unit>,
+    /// Where the datapoint was recorded, see [crate::ChronographBuilder::with_source_locations]
+    pub location: Option<SourceLocation>,
+    /// The instant a value was measured at, when it was recorded later, see [crate::Span::record_value_at]
+    pub instant: Option<u64>,
+}
+
+/// A location in the source code, such as the line that recorded a datapoint
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize,
+)]
+pub struct SourceLocation {
+    /// The source file path, which is registered in the [crate::registry]
+    pub file: DatapointId,
+    pub line: u32,
+}
+
+/// The OS thread a span was started on
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize,
+)]
+pub struct ThreadInfo {
+    /// The kernel thread id on Linux and Android, or an id unique within the process elsewhere
+    pub id: u64,
+    /// The thread name, which is registered in the [crate::registry]
+    pub name: Option<DatapointId>,
+}
+
+/// A reference to another span, possibly from another trace
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize,
+)]
+pub struct SpanLink {
+    pub span_id: u64,
+    pub trace_id: u128,
+}
+
+/// A key-value pair describing a span as a whole, rather than a point in its timeline
+#[derive(Debug, Clone, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+pub struct Attribute {
+    pub key: DatapointId,
+    pub value: RecordValue,
 }
 
 #[derive(
@@ -33,6 +481,16 @@ pub struct DatapointId {
 }
 
 #[derive(Debug, Clone, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+// bounds are spelled out because RecordValue is recursive through RecordValue::List
+#[rkyv(serialize_bounds(
+    __S: rkyv::ser::Writer + rkyv::ser::Allocator,
+    __S::Error: rkyv::rancor::Source,
+))]
+#[rkyv(deserialize_bounds(__D::Error: rkyv::rancor::Source))]
+#[rkyv(bytecheck(bounds(
+    __C: rkyv::validation::ArchiveContext,
+    __C::Error: rkyv::rancor::Source,
+)))]
 pub enum RecordValue {
     /// Monotonic instant (accurate nanosecond timer elapsed from when the Cronograph was started)
     Instant(u64),
@@ -56,6 +514,26 @@ pub enum RecordValue {
     F32(f32),
     /// An arbitrary f64 value
     F64(f64),
+    /// An arbitrary bool value
+    Bool(bool),
+    /// An arbitrary binary payload
+    Bytes(Vec<u8>),
+    /// An elapsed duration measured by the caller (as nanoseconds)
+    Duration(u64),
+    /// Multiple values, such as the latency of each retry. Elements may be of different types, and are not validated.
+    List(#[rkyv(omit_bounds)] Vec<RecordValue>),
+}
+
+/// The unit of a numeric [RecordValue]
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize,
+)]
+pub enum Unit {
+    Nanoseconds,
+    Bytes,
+    Count,
+    /// An application-defined unit
+    Custom(u16),
 }
 
 impl From<&SpanBatch> for AlignedVec {
@@ -93,6 +571,547 @@ impl TryFrom<&[u8]> for SpanBatch {
     }
 }
 
+/// The serialization format used by [SpanBatch::encode] and [SpanBatch::decode]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BatchEncoding {
+    /// Plain rkyv serialization, identical to `Vec::<u8>::from(batch)`
+    #[default]
+    Standard,
+    /// Instant records are stored as varints relative to the previous instant of their span, starting from the span's start instant.
+    /// This greatly reduces payload size for spans with many instant records, at the cost of an extra decoding pass.
+    DeltaInstants,
+    /// Records sharing a datapoint id, such as per-iteration instants, store the id once with their positions and values packed.
+    /// Repeated instants are packed as delta varints, reducing payload size for spans recording the same datapoint many times.
+    /// See [crate::ChronographBuilder::with_packed_instants] to also pack instants in memory while spans are recorded.
+    PackedRepeats,
+}
+
+impl SpanBatch {
+    /// The span with the given id
+    pub fn find_span(&self, span_id: u64) -> Option<&SpanData> {
+        self.spans.iter().find(|x| x.span_id == span_id)
+    }
+
+    /// Spans with the given name
+    pub fn spans_named(&self, name: impl Into<DatapointId>) -> impl Iterator<Item = &SpanData> {
+        let name = Some(name.into());
+        self.spans.iter().filter(move |x| x.name == name)
+    }
+
+    /// Spans of the given trace
+    pub fn trace(&self, trace_id: u128) -> impl Iterator<Item = &SpanData> {
+        self.spans.iter().filter(move |x| x.trace_id == trace_id)
+    }
+
+    /// Spans started without a parent
+    pub fn roots(&self) -> impl Iterator<Item = &SpanData> {
+        self.spans.iter().filter(|x| x.is_root())
+    }
+
+    /// Direct children of the span with the given id
+    pub fn children(&self, span_id: u64) -> impl Iterator<Item = &SpanData> {
+        self.spans
+            .iter()
+            .filter(move |x| x.parent_span_id == Some(span_id))
+    }
+
+    /// Append the spans of another batch, adding the names of its dictionary that this batch is missing.
+    ///
+    /// The metadata of this batch is kept.
+    pub fn merge(&mut self, other: SpanBatch) {
+        self.spans.extend(other.spans);
+        for entry in other.dictionary {
+            if !self.dictionary.iter().any(|x| x.id == entry.id) {
+                self.dictionary.push(entry);
+            }
+        }
+    }
+
+    /// Split into the first `n` spans and the rest, each keeping the metadata and dictionary.
+    ///
+    /// Every span goes to the first batch when there are fewer than `n`.
+    pub fn split_at(mut self, n: usize) -> (SpanBatch, SpanBatch) {
+        let rest = self.spans.split_off(n.min(self.spans.len()));
+        let rest = SpanBatch {
+            spans: rest,
+            metadata: self.metadata.clone(),
+            dictionary: self.dictionary.clone(),
+        };
+        (self, rest)
+    }
+
+    /// An estimate of the bytes of the batch serialized with the given encoding, see [SpanData::serialized_size_hint]
+    pub fn serialized_size_hint(&self, encoding: BatchEncoding) -> usize {
+        let batch_size = match encoding {
+            BatchEncoding::Standard => size_of::<ArchivedSpanBatch>(),
+            BatchEncoding::DeltaInstants => size_of::<ArchivedDeltaSpanBatch>(),
+            BatchEncoding::PackedRepeats => size_of::<ArchivedPackedSpanBatch>(),
+        };
+        let packed_overhead = match encoding {
+            BatchEncoding::PackedRepeats => {
+                size_of::<ArchivedPackedSpanData>() - size_of::<ArchivedSpanData>()
+            }
+            _ => 0,
+        };
+        batch_size
+            + out_of_line_size(self.metadata.sampling.sampler.len())
+            + self
+                .dictionary
+                .iter()
+                .map(|x| size_of::<ArchivedDictionaryEntry>() + out_of_line_size(x.name.len()))
+                .sum::<usize>()
+            + self
+                .spans
+                .iter()
+                .map(|x| x.serialized_size_hint(encoding) + packed_overhead)
+                .sum::<usize>()
+    }
+
+    /// Sort the spans by start instant, keeping the order of spans that started at the same instant.
+    ///
+    /// Instants are only comparable within a process, so batches merged from several processes
+    /// should rather be ordered by [SpanData::start_unix_time], such as with [crate::collector::TimelineCollector].
+    pub fn sort_by_start_instant(&mut self) {
+        self.spans.sort_by_key(|x| x.start_instant);
+    }
+
+    /// Serialize the batch with the given [BatchEncoding]
+    pub fn encode(&self, encoding: BatchEncoding) -> Vec<u8> {
+        match encoding {
+            BatchEncoding::Standard => self.into(),
+            BatchEncoding::DeltaInstants => {
+                let batch = DeltaSpanBatch {
+                    spans: self.spans.iter().map(DeltaSpanData::from).collect(),
+                    metadata: self.metadata.clone(),
+                    dictionary: self.dictionary.clone(),
+                };
+                rkyv::to_bytes::<rkyv::rancor::Error>(&batch)
+                    .unwrap_or_default()
+                    .into_vec()
+            }
+            BatchEncoding::PackedRepeats => {
+                let batch = PackedSpanBatch {
+                    spans: self.spans.iter().map(PackedSpanData::from).collect(),
+                    metadata: self.metadata.clone(),
+                    dictionary: self.dictionary.clone(),
+                };
+                rkyv::to_bytes::<rkyv::rancor::Error>(&batch)
+                    .unwrap_or_default()
+                    .into_vec()
+            }
+        }
+    }
+
+    /// Deserialize a batch that was serialized by [SpanBatch::encode] with the same [BatchEncoding]
+    pub fn decode(bytes: &[u8], encoding: BatchEncoding) -> Result<Self, rkyv::rancor::Error> {
+        match encoding {
+            BatchEncoding::Standard => Self::try_from(bytes),
+            BatchEncoding::DeltaInstants => {
+                let batch = rkyv::from_bytes::<DeltaSpanBatch, rkyv::rancor::Error>(bytes)?;
+                Ok(Self {
+                    spans: batch
+                        .spans
+                        .into_iter()
+                        .map(SpanData::try_from)
+                        .collect::<Result<_, _>>()?,
+                    metadata: batch.metadata,
+                    dictionary: batch.dictionary,
+                })
+            }
+            BatchEncoding::PackedRepeats => {
+                let batch = rkyv::from_bytes::<PackedSpanBatch, rkyv::rancor::Error>(bytes)?;
+                Ok(Self {
+                    spans: batch
+                        .spans
+                        .into_iter()
+                        .map(SpanData::try_from)
+                        .collect::<Result<_, _>>()?,
+                    metadata: batch.metadata,
+                    dictionary: batch.dictionary,
+                })
+            }
+        }
+    }
+}
+
+#[derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+struct DeltaSpanBatch {
+    spans: Vec<DeltaSpanData>,
+    metadata: BatchMetadata,
+    dictionary: Vec<DictionaryEntry>,
+}
+
+/// A [SpanData] with its instant records moved out of `span.records` and delta-encoded
+#[derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+struct DeltaSpanData {
+    span: SpanData,
+    /// Bitmask of which positions in the original records were instants
+    instant_mask: Vec<u8>,
+    instant_ids: Vec<DatapointId>,
+    /// Zigzag varint deltas of each instant from the previous one
+    instant_deltas: Vec<u8>,
+}
+
+impl From<&SpanData> for DeltaSpanData {
+    fn from(value: &SpanData) -> Self {
+        let mut span = SpanData {
+            records: Vec::new(),
+            ..value.clone()
+        };
+        let mut instant_mask = vec![0u8; value.records.len().div_ceil(8)];
+        let mut instant_ids = Vec::new();
+        let mut instant_deltas = Vec::new();
+        let mut previous = value.start_instant;
+        for (i, record) in value.records.iter().enumerate() {
+            match (&record.value, record.unit, record.location, record.instant) {
+                (RecordValue::Instant(instant), None, None, None) => {
+                    instant_mask[i / 8] |= 1 << (i % 8);
+                    instant_ids.push(record.datapoint_id);
+                    write_varint(
+                        &mut instant_deltas,
+                        zigzag_encode(instant.wrapping_sub(previous) as i64),
+                    );
+                    previous = *instant;
+                }
+                _ => span.records.push(record.clone()),
+            }
+        }
+        Self {
+            span,
+            instant_mask,
+            instant_ids,
+            instant_deltas,
+        }
+    }
+}
+
+impl TryFrom<DeltaSpanData> for SpanData {
+    type Error = rkyv::rancor::Error;
+    fn try_from(value: DeltaSpanData) -> Result<Self, Self::Error> {
+        let record_count = value.span.records.len() + value.instant_ids.len();
+        let mut records = Vec::with_capacity(record_count);
+        let mut other_records = value.span.records.into_iter();
+        let mut instant_ids = value.instant_ids.into_iter();
+        let mut instant_deltas = value.instant_deltas.as_slice();
+        let mut previous = value.span.start_instant;
+        for i in 0..record_count {
+            let is_instant = value
+                .instant_mask
+                .get(i / 8)
+                .is_some_and(|x| x & (1 << (i % 8)) != 0);
+            let record = if is_instant {
+                let delta = read_varint(&mut instant_deltas).ok_or_else(malformed)?;
+                previous = previous.wrapping_add(zigzag_decode(delta) as u64);
+                RecordData {
+                    datapoint_id: instant_ids.next().ok_or_else(malformed)?,
+                    value: RecordValue::Instant(previous),
+                    unit: None,
+                    location: None,
+                    instant: None,
+                }
+            } else {
+                other_records.next().ok_or_else(malformed)?
+            };
+            records.push(record);
+        }
+        Ok(SpanData {
+            records,
+            ..value.span
+        })
+    }
+}
+
+#[derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+struct PackedSpanBatch {
+    spans: Vec<PackedSpanData>,
+    metadata: BatchMetadata,
+    dictionary: Vec<DictionaryEntry>,
+}
+
+/// A [SpanData] with its repeated records moved out of `span.records` and packed by datapoint id
+#[derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+struct PackedSpanData {
+    span: SpanData,
+    groups: Vec<PackedRecords>,
+}
+
+/// Every record of a span with the same datapoint id and unit
+#[derive(Debug, Clone, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+struct PackedRecords {
+    datapoint_id: DatapointId,
+    unit: Option<Unit>,
+    /// Varint gaps between the positions of the records in the original records
+    positions: Vec<u8>,
+    values: PackedValues,
+}
+
+#[derive(Debug, Clone, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+enum PackedValues {
+    /// Zigzag varint deltas of each instant from the previous one, starting from the span's start instant
+    Instants(Vec<u8>),
+    Values(Vec<RecordValue>),
+}
+
+impl From<&SpanData> for PackedSpanData {
+    fn from(value: &SpanData) -> Self {
+        let mut span = SpanData {
+            records: Vec::new(),
+            ..value.clone()
+        };
+        // records with a location or instant are never packed, since it would have to be repeated anyway
+        let mut counts: HashMap<(DatapointId, Option<Unit>), usize> = HashMap::new();
+        for record in value
+            .records
+            .iter()
+            .filter(|x| x.location.is_none() && x.instant.is_none())
+        {
+            *counts
+                .entry((record.datapoint_id, record.unit))
+                .or_default() += 1;
+        }
+        let mut groups: Vec<PackedRecords> = Vec::new();
+        let mut group_index: HashMap<(DatapointId, Option<Unit>), (usize, usize, u64)> =
+            HashMap::new();
+        for (position, record) in value.records.iter().enumerate() {
+            let key = (record.datapoint_id, record.unit);
+            if record.location.is_some()
+                || record.instant.is_some()
+                || counts.get(&key).copied().unwrap_or_default() < 2
+            {
+                span.records.push(record.clone());
+                continue;
+            }
+            // (group, previous position, previous instant)
+            let (group, previous_position, previous_instant) =
+                group_index.entry(key).or_insert_with(|| {
+                    groups.push(PackedRecords {
+                        datapoint_id: record.datapoint_id,
+                        unit: record.unit,
+                        positions: Vec::new(),
+                        values: PackedValues::Instants(Vec::new()),
+                    });
+                    (groups.len() - 1, 0, value.start_instant)
+                });
+            let group = &mut groups[*group];
+            write_varint(&mut group.positions, (position - *previous_position) as u64);
+            *previous_position = position;
+            match (&mut group.values, &record.value) {
+                (PackedValues::Instants(deltas), RecordValue::Instant(instant)) => {
+                    write_varint(
+                        deltas,
+                        zigzag_encode(instant.wrapping_sub(*previous_instant) as i64),
+                    );
+                    *previous_instant = *instant;
+                }
+                (PackedValues::Instants(deltas), _) => {
+                    // a group only stays packed as instants while every value is an instant
+                    let mut instants = deltas.as_slice();
+                    let mut instant = value.start_instant;
+                    let mut values = Vec::new();
+                    while let Some(delta) = read_varint(&mut instants) {
+                        instant = instant.wrapping_add(zigzag_decode(delta) as u64);
+                        values.push(RecordValue::Instant(instant));
+                    }
+                    values.push(record.value.clone());
+                    group.values = PackedValues::Values(values);
+                }
+                (PackedValues::Values(values), _) => values.push(record.value.clone()),
+            }
+        }
+        Self { span, groups }
+    }
+}
+
+impl TryFrom<PackedSpanData> for SpanData {
+    type Error = rkyv::rancor::Error;
+    fn try_from(value: PackedSpanData) -> Result<Self, Self::Error> {
+        let records = unpack_records(&value.groups, value.span.records, value.span.start_instant)
+            .ok_or_else(malformed)?;
+        Ok(SpanData {
+            records,
+            ..value.span
+        })
+    }
+}
+
+/// Restore packed records to their positions among the other records of a span
+fn unpack_records(
+    groups: &[PackedRecords],
+    others: Vec<RecordData>,
+    start_instant: u64,
+) -> Option<Vec<RecordData>> {
+    let unpacked: Vec<Vec<RecordValue>> = groups
+        .iter()
+        .map(|group| match &group.values {
+            PackedValues::Instants(deltas) => {
+                let mut deltas = deltas.as_slice();
+                let mut instant = start_instant;
+                let mut values = Vec::new();
+                while let Some(delta) = read_varint(&mut deltas) {
+                    instant = instant.wrapping_add(zigzag_decode(delta) as u64);
+                    values.push(RecordValue::Instant(instant));
+                }
+                values
+            }
+            PackedValues::Values(values) => values.clone(),
+        })
+        .collect();
+    // positions come from untrusted input, so they are checked against the records there are before allocating
+    let total = others.len() + unpacked.iter().map(Vec::len).sum::<usize>();
+    let mut records: Vec<Option<RecordData>> = Vec::new();
+    for (group, values) in groups.iter().zip(unpacked) {
+        let mut positions = group.positions.as_slice();
+        let mut position: usize = 0;
+        for value in values {
+            let delta = usize::try_from(read_varint(&mut positions)?).ok()?;
+            position = position.checked_add(delta)?;
+            if position >= total {
+                return None;
+            }
+            if records.len() <= position {
+                records.resize(position + 1, None);
+            }
+            if records[position].is_some() {
+                return None;
+            }
+            records[position] = Some(RecordData {
+                datapoint_id: group.datapoint_id,
+                value,
+                unit: group.unit,
+                location: None,
+                instant: None,
+            });
+        }
+    }
+    let mut others = others.into_iter();
+    let mut filled: Vec<RecordData> = records
+        .into_iter()
+        .map(|x| x.or_else(|| others.next()))
+        .collect::<Option<_>>()?;
+    filled.extend(others);
+    Some(filled)
+}
+
+/// The plain instant records of a live span packed by datapoint id, see [crate::ChronographBuilder::with_packed_instants]
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PackedInstants {
+    groups: Vec<PackedRecords>,
+    /// The position and instant of the last record of each group
+    previous: Vec<(usize, u64)>,
+    len: usize,
+}
+
+impl PackedInstants {
+    /// The number of packed records
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// See [SpanData::estimated_serialized_size], as the packed instants take once unpacked
+    pub(crate) fn estimated_serialized_size(&self) -> usize {
+        self.len * size_of::<ArchivedRecordData>()
+    }
+
+    /// Pack an instant recorded at `position` among all records of a span that started at `start_instant`
+    pub(crate) fn push(
+        &mut self,
+        datapoint_id: DatapointId,
+        instant: u64,
+        position: usize,
+        start_instant: u64,
+    ) {
+        let index = match self
+            .groups
+            .iter()
+            .position(|x| x.datapoint_id == datapoint_id)
+        {
+            Some(index) => index,
+            None => {
+                self.groups.push(PackedRecords {
+                    datapoint_id,
+                    unit: None,
+                    positions: Vec::new(),
+                    values: PackedValues::Instants(Vec::new()),
+                });
+                self.previous.push((0, start_instant));
+                self.groups.len() - 1
+            }
+        };
+        let (previous_position, previous_instant) = &mut self.previous[index];
+        let group = &mut self.groups[index];
+        write_varint(&mut group.positions, (position - *previous_position) as u64);
+        if let PackedValues::Instants(deltas) = &mut group.values {
+            write_varint(
+                deltas,
+                zigzag_encode(instant.wrapping_sub(*previous_instant) as i64),
+            );
+        }
+        *previous_position = position;
+        *previous_instant = instant;
+        self.len += 1;
+    }
+
+    /// The records of a span that started at `start_instant` in the order they were recorded,
+    /// with the packed instants restored among the `others`
+    pub(crate) fn unpack(&self, others: Vec<RecordData>, start_instant: u64) -> Vec<RecordData> {
+        if self.len == 0 {
+            return others;
+        }
+        unpack_records(&self.groups, others, start_instant)
+            .expect("packed instants are positioned among the span's records")
+    }
+}
+
+#[derive(Debug)]
+struct MalformedEncoding;
+
+impl Display for MalformedEncoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "malformed span batch encoding")
+    }
+}
+
+impl std::error::Error for MalformedEncoding {}
+
+fn malformed() -> rkyv::rancor::Error {
+    rkyv::rancor::Error::new(MalformedEncoding)
+}
+
+fn zigzag_encode(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn zigzag_decode(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn varint_len(value: u64) -> usize {
+    ((u64::BITS - value.leading_zeros()) as usize)
+        .div_ceil(7)
+        .max(1)
+}
+
+fn read_varint(bytes: &mut &[u8]) -> Option<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (byte, rest) = bytes.split_first()?;
+        *bytes = rest;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
 impl From<&SpanData> for AlignedVec {
     fn from(value: &SpanData) -> Self {
         rkyv::to_bytes::<rkyv::rancor::Error>(value).unwrap_or_default()
@@ -134,12 +1153,161 @@ impl From<u64> for DatapointId {
     }
 }
 
+impl DatapointId {
+    /// The id of a name, the same as converting it with `From<&str>`.
+    ///
+    /// It can be evaluated in a const context, which the [crate::macros] do for string literal ids
+    /// so they are not hashed on every record.
+    pub const fn from_name(name: &str) -> Self {
+        Self {
+            value: zwohash(name.as_bytes()),
+        }
+    }
+}
+
 impl From<&str> for DatapointId {
     fn from(value: &str) -> Self {
-        let mut hasher = ZwoHasher::default();
-        hasher.write(value.as_bytes());
-        Self {
-            value: hasher.finish(),
+        Self::from_name(value)
+    }
+}
+
+// A const port of `ZwoHasher::write` followed by `ZwoHasher::finish`, which must stay bit-for-bit identical,
+// including its dependence on the target's pointer width and endianness.
+#[cfg(target_pointer_width = "64")]
+const ZWOHASH_M: usize = 0x2545f4914f6cdd1d;
+#[cfg(target_pointer_width = "32")]
+const ZWOHASH_M: usize = 0x2c9277b5;
+#[cfg(target_pointer_width = "64")]
+const ZWOHASH_R: u32 = 41;
+#[cfg(target_pointer_width = "32")]
+const ZWOHASH_R: u32 = 21;
+#[cfg(target_pointer_width = "64")]
+type ZwohashWide = u128;
+#[cfg(target_pointer_width = "32")]
+type ZwohashWide = u64;
+
+const fn zwohash(bytes: &[u8]) -> u64 {
+    const USIZE_BYTES: usize = size_of::<usize>();
+    const fn write_usize(state: usize, value: usize) -> usize {
+        state.wrapping_mul(ZWOHASH_M).rotate_right(ZWOHASH_R) ^ value
+    }
+
+    const fn read_usize(bytes: &[u8], offset: usize) -> usize {
+        let mut chunk = [0u8; USIZE_BYTES];
+        let mut i = 0;
+        while i < USIZE_BYTES {
+            chunk[i] = bytes[offset + i];
+            i += 1;
+        }
+        usize::from_ne_bytes(chunk)
+    }
+    let len = bytes.len();
+    let mut state = 0;
+    if len >= USIZE_BYTES {
+        let mut offset = 0;
+        while len - offset > USIZE_BYTES {
+            state = write_usize(state, read_usize(bytes, offset));
+            offset += USIZE_BYTES;
+        }
+        // the last chunk overlaps the previous one when the length is not a multiple of the word size
+        state = write_usize(state, read_usize(bytes, len - USIZE_BYTES));
+    } else if USIZE_BYTES == 8 && len >= 4 {
+        let low = u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as u64;
+        let high = u32::from_ne_bytes([
+            bytes[len - 4],
+            bytes[len - 3],
+            bytes[len - 2],
+            bytes[len - 1],
+        ]) as u64;
+        state = write_usize(state, (low | (high << 32)) as usize);
+    } else if len >= 2 {
+        let low = u16::from_ne_bytes([bytes[0], bytes[1]]) as usize;
+        let high = u16::from_ne_bytes([bytes[len - 2], bytes[len - 1]]) as usize;
+        state = write_usize(state, low | (high << 16));
+    } else if len >= 1 {
+        state = write_usize(state, bytes[0] as usize);
+    }
+    let wide = (state as ZwohashWide) * (ZWOHASH_M as ZwohashWide);
+    (wide as usize).wrapping_sub((wide >> usize::BITS) as usize) as u64
+}
+
+/// See [SpanData::estimated_serialized_size], which is shared with [crate::Span::current_size]
+pub(crate) fn estimated_serialized_size(
+    records: &[RecordData],
+    attributes: &[Attribute],
+    links: &[SpanLink],
+    status: &SpanStatus,
+    backtrace: Option<&str>,
+) -> usize {
+    let status_size = match status {
+        SpanStatus::Error(Some(message)) => out_of_line_size(message.len()),
+        _ => 0,
+    };
+    size_of::<ArchivedSpanData>()
+        + records.len() * size_of::<ArchivedRecordData>()
+        + records
+            .iter()
+            .map(|x| x.value.estimated_out_of_line_size())
+            .sum::<usize>()
+        + attributes.len() * size_of::<ArchivedAttribute>()
+        + attributes
+            .iter()
+            .map(|x| x.value.estimated_out_of_line_size())
+            .sum::<usize>()
+        + links.len() * size_of::<ArchivedSpanLink>()
+        + status_size
+        + backtrace
+            .map(|x| out_of_line_size(x.len()))
+            .unwrap_or_default()
+}
+
+/// Archived strings of up to 8 bytes are stored inline
+fn out_of_line_size(len: usize) -> usize {
+    match len {
+        0..=8 => 0,
+        _ => len,
+    }
+}
+
+impl RecordValue {
+    /// Bytes of the archived value stored apart from its fixed-size part, such as string contents
+    fn estimated_out_of_line_size(&self) -> usize {
+        match self {
+            Self::Utf8String(x) => out_of_line_size(x.len()),
+            Self::Bytes(x) => x.len(),
+            Self::List(x) => {
+                x.len() * size_of::<ArchivedRecordValue>()
+                    + x.iter()
+                        .map(|x| x.estimated_out_of_line_size())
+                        .sum::<usize>()
+            }
+            _ => 0,
+        }
+    }
+
+    /// A partition key derived from this value, see [crate::Span::set_partition_key].
+    ///
+    /// Integers are used as-is and strings and bytes are hashed with [zwohash], so the key is consistent across processes.
+    /// Lists have no partition key.
+    pub fn partition_key(&self) -> Option<u64> {
+        let hash = |bytes: &[u8]| {
+            let mut hasher = ZwoHasher::default();
+            hasher.write(bytes);
+            hasher.finish()
+        };
+        match self {
+            Self::Instant(x) | Self::U64(x) | Self::Duration(x) => Some(*x),
+            Self::UnixTime(x) | Self::I64(x) => Some(*x as u64),
+            Self::I32(x) => Some(*x as u64),
+            Self::U32(x) => Some(*x as u64),
+            Self::I128(x) => Some(*x as u64 ^ (*x >> 64) as u64),
+            Self::U128(x) => Some(*x as u64 ^ (*x >> 64) as u64),
+            Self::F32(x) => Some(x.to_bits() as u64),
+            Self::F64(x) => Some(x.to_bits()),
+            Self::Bool(x) => Some(*x as u64),
+            Self::Utf8String(x) => Some(hash(x.as_bytes())),
+            Self::Bytes(x) => Some(hash(x)),
+            Self::List(_) => None,
         }
     }
 }
@@ -155,6 +1323,12 @@ impl From<SystemTime> for RecordValue {
     }
 }
 
+impl From<Duration> for RecordValue {
+    fn from(value: Duration) -> Self {
+        Self::Duration(value.as_nanos() as u64)
+    }
+}
+
 impl From<usize> for RecordValue {
     fn from(value: usize) -> Self {
         Self::U64(value as u64)
@@ -214,3 +1388,33 @@ impl From<f64> for RecordValue {
         Self::F64(value)
     }
 }
+
+impl From<bool> for RecordValue {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+impl From<Vec<u8>> for RecordValue {
+    fn from(value: Vec<u8>) -> Self {
+        Self::Bytes(value)
+    }
+}
+
+impl From<&[u8]> for RecordValue {
+    fn from(value: &[u8]) -> Self {
+        Self::Bytes(value.to_vec())
+    }
+}
+
+impl From<Vec<RecordValue>> for RecordValue {
+    fn from(value: Vec<RecordValue>) -> Self {
+        Self::List(value)
+    }
+}
+
+impl<T: Into<RecordValue>> FromIterator<T> for RecordValue {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self::List(iter.into_iter().map(Into::into).collect())
+    }
+}
@@ -0,0 +1,174 @@
+//! Per-thread staging of ended spans, see [crate::ChronographBuilder::with_staging].
+//!
+//! Ending a span only pushes it to a buffer owned by the current thread. Full buffers are handed to a processing
+//! thread in chunks, which runs the processors and recorder, so the drop path never touches a shared queue.
+
+use std::{
+    cell::RefCell,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex, Weak,
+    },
+};
+
+use crate::{schema::SpanData, ChronographContext};
+
+static NEXT_STAGING_ID: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    static BUFFERS: RefCell<Vec<StagingBuffer>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Spans staged by one thread, only contended when another thread flushes it
+type SharedSpans = Arc<Mutex<Vec<SpanData>>>;
+
+/// The sending side of a staging pipeline, owned by a chronograph context
+#[derive(Debug)]
+pub(crate) struct Staging {
+    id: u64,
+    chunk_size: usize,
+    tx: Sender<StagingMessage>,
+    /// The buffer of every thread that has staged a span, so [Staging::flush] can hand them all over
+    buffers: Mutex<Vec<Weak<Mutex<Vec<SpanData>>>>>,
+}
+
+#[derive(Debug)]
+enum StagingMessage {
+    Chunk(Vec<SpanData>),
+    /// Reply once every chunk sent before it has been processed
+    Flush(Sender<()>),
+}
+
+/// Spans staged by one thread for one [Staging], handed over when full or when the thread exits
+struct StagingBuffer {
+    staging_id: u64,
+    chunk_size: usize,
+    tx: Sender<StagingMessage>,
+    spans: SharedSpans,
+}
+
+impl Staging {
+    pub(crate) fn new(chunk_size: usize) -> (Self, StagingReceiver) {
+        let (tx, rx) = mpsc::channel();
+        let staging = Self {
+            id: NEXT_STAGING_ID.fetch_add(1, Ordering::Relaxed),
+            chunk_size: chunk_size.max(1),
+            tx,
+            buffers: Mutex::new(Vec::new()),
+        };
+        (staging, StagingReceiver(rx))
+    }
+
+    pub(crate) fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    /// Push a span to the current thread's buffer, handing the buffer over when it is full
+    pub(crate) fn stage(&self, span: SpanData) {
+        let mut span = Some(span);
+        BUFFERS
+            .try_with(|buffers| {
+                let mut buffers = buffers.borrow_mut();
+                let buffer = match buffers.iter().position(|x| x.staging_id == self.id) {
+                    Some(i) => &mut buffers[i],
+                    None => {
+                        let spans =
+                            SharedSpans::new(Mutex::new(Vec::with_capacity(self.chunk_size)));
+                        let mut registered = self.buffers.lock().unwrap_or_else(|e| e.into_inner());
+                        // forget buffers of threads that have exited
+                        registered.retain(|x| x.strong_count() > 0);
+                        registered.push(Arc::downgrade(&spans));
+                        buffers.push(StagingBuffer {
+                            staging_id: self.id,
+                            chunk_size: self.chunk_size,
+                            tx: self.tx.clone(),
+                            spans,
+                        });
+                        buffers.last_mut().expect("buffer was just pushed")
+                    }
+                };
+                let mut spans = buffer.spans.lock().unwrap_or_else(|e| e.into_inner());
+                spans.extend(span.take());
+                if spans.len() >= buffer.chunk_size {
+                    hand_over(&mut spans, buffer.chunk_size, &buffer.tx);
+                }
+            })
+            .ok();
+        // the thread-local is gone while the thread exits, so send the span on its own
+        if let Some(span) = span {
+            self.tx.send(StagingMessage::Chunk(vec![span])).ok();
+        }
+    }
+
+    /// Hand over the buffer of every thread, then block until the processing thread has caught up
+    pub(crate) fn flush(&self) {
+        let buffers: Vec<SharedSpans> = self
+            .buffers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .filter_map(Weak::upgrade)
+            .collect();
+        for spans in buffers {
+            hand_over(
+                &mut spans.lock().unwrap_or_else(|e| e.into_inner()),
+                self.chunk_size,
+                &self.tx,
+            );
+        }
+        let (done_tx, done_rx) = mpsc::channel();
+        if self.tx.send(StagingMessage::Flush(done_tx)).is_ok() {
+            done_rx.recv().ok();
+        }
+    }
+}
+
+/// Send the staged spans to the processing thread as one chunk
+fn hand_over(spans: &mut Vec<SpanData>, chunk_size: usize, tx: &Sender<StagingMessage>) {
+    if !spans.is_empty() {
+        let chunk = std::mem::replace(spans, Vec::with_capacity(chunk_size));
+        tx.send(StagingMessage::Chunk(chunk)).ok();
+    }
+}
+
+impl Drop for StagingBuffer {
+    fn drop(&mut self) {
+        // the thread is exiting, so hand over whatever it staged
+        let mut spans = self.spans.lock().unwrap_or_else(|e| e.into_inner());
+        if !spans.is_empty() {
+            self.tx
+                .send(StagingMessage::Chunk(std::mem::take(&mut spans)))
+                .ok();
+        }
+    }
+}
+
+/// The receiving side of a staging pipeline, consumed when the processing thread is spawned
+#[derive(Debug)]
+pub(crate) struct StagingReceiver(Receiver<StagingMessage>);
+
+impl StagingReceiver {
+    /// Spawn the processing thread, which only holds a weak reference so it never keeps the context alive
+    pub(crate) fn spawn(self, context: Weak<ChronographContext>) {
+        std::thread::Builder::new()
+            .name("chronograph staging".to_owned())
+            .spawn(move || {
+                while let Ok(message) = self.0.recv() {
+                    match message {
+                        StagingMessage::Chunk(chunk) => {
+                            if let Some(context) = context.upgrade() {
+                                for span in chunk {
+                                    context.process_span(span);
+                                }
+                            }
+                        }
+                        StagingMessage::Flush(done_tx) => {
+                            done_tx.send(()).ok();
+                        }
+                    }
+                }
+            })
+            .expect("could not spawn std thread");
+    }
+}
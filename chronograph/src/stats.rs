@@ -0,0 +1,105 @@
+//! Statistics over the spans of a [SpanBatch], for simple analysis without exporting to another system.
+//!
+//! # Example
+//! ```rust
+//! use std::time::Duration;
+//! use chronograph::schema::{SpanBatch, SpanData};
+//! use chronograph::stats;
+//!
+//! let span = |end_instant| SpanData {
+//!     start_instant: 0,
+//!     end_instant,
+//!     ..Default::default()
+//! };
+//! let batch = SpanBatch {
+//!     spans: (1..=100).map(|x| span(x * 1_000)).collect(),
+//!     metadata: Default::default(),
+//!     dictionary: Vec::new(),
+//! };
+//! let summary = &stats::duration_stats(&batch)[&None];
+//! assert_eq!(summary.count, 100);
+//! assert_eq!(summary.p50, Duration::from_micros(50));
+//! assert_eq!(summary.max, Duration::from_micros(100));
+//! ```
+
+use std::{collections::HashMap, time::Duration};
+
+use crate::schema::{DatapointId, RecordValue, SpanBatch};
+
+/// The distribution of a set of durations
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Summary {
+    pub count: usize,
+    pub min: Duration,
+    pub max: Duration,
+    pub mean: Duration,
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub p999: Duration,
+}
+
+impl Summary {
+    /// Summarize durations in nanoseconds, or None when there are none
+    pub fn from_nanos(mut nanos: Vec<u64>) -> Option<Self> {
+        if nanos.is_empty() {
+            return None;
+        }
+        nanos.sort_unstable();
+        let sum: u128 = nanos.iter().map(|x| *x as u128).sum();
+        Some(Self {
+            count: nanos.len(),
+            min: Duration::from_nanos(nanos[0]),
+            max: Duration::from_nanos(nanos[nanos.len() - 1]),
+            mean: Duration::from_nanos((sum / nanos.len() as u128) as u64),
+            p50: percentile(&nanos, 0.5),
+            p90: percentile(&nanos, 0.9),
+            p99: percentile(&nanos, 0.99),
+            p999: percentile(&nanos, 0.999),
+        })
+    }
+}
+
+/// The nearest-rank percentile of sorted values
+fn percentile(sorted: &[u64], percentile: f64) -> Duration {
+    let rank = (percentile * sorted.len() as f64).ceil() as usize;
+    Duration::from_nanos(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+/// The distribution of span durations, by span name
+pub fn duration_stats(batch: &SpanBatch) -> HashMap<Option<DatapointId>, Summary> {
+    let mut durations: HashMap<Option<DatapointId>, Vec<u64>> = HashMap::new();
+    for span in batch.spans.iter() {
+        durations
+            .entry(span.name)
+            .or_default()
+            .push(span.end_instant.saturating_sub(span.start_instant));
+    }
+    summarize(durations)
+}
+
+/// The distribution of the time between each instant datapoint and the previous instant of its span,
+/// or the start of the span for the first instant, by datapoint id
+pub fn instant_delta_stats(batch: &SpanBatch) -> HashMap<DatapointId, Summary> {
+    let mut deltas: HashMap<DatapointId, Vec<u64>> = HashMap::new();
+    for span in batch.spans.iter() {
+        let mut previous = span.start_instant;
+        for record in span.records.iter() {
+            if let RecordValue::Instant(instant) = record.value {
+                deltas
+                    .entry(record.datapoint_id)
+                    .or_default()
+                    .push(instant.saturating_sub(previous));
+                previous = instant;
+            }
+        }
+    }
+    summarize(deltas)
+}
+
+fn summarize<K: Eq + std::hash::Hash>(values: HashMap<K, Vec<u64>>) -> HashMap<K, Summary> {
+    values
+        .into_iter()
+        .filter_map(|(key, values)| Some((key, Summary::from_nanos(values)?)))
+        .collect()
+}
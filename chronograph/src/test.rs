@@ -0,0 +1,78 @@
+//! Utilities for testing instrumented code.
+//!
+//! # Example
+//! ```rust
+//! use chronograph::macros::*;
+//! use chronograph::test::with_capture;
+//!
+//! let spans = with_capture(|| {
+//!     start_span!();
+//!     record_value!("count", 42);
+//!     end_span!();
+//! });
+//! assert_eq!(spans.len(), 1);
+//! ```
+
+use std::{
+    mem::take,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    global::with_scoped_global, local, recorder::RecordSpan, recorder::SpanRecorder,
+    schema::SpanData, Chronograph, Span,
+};
+
+/// A recorder that keeps every span in memory
+#[derive(Debug, Clone, Default)]
+pub struct CapturingRecorder {
+    spans: Arc<Mutex<Vec<SpanData>>>,
+}
+
+impl CapturingRecorder {
+    /// Take all spans captured so far
+    pub fn take(&self) -> Vec<SpanData> {
+        std::mem::take(&mut *self.spans.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+impl RecordSpan for CapturingRecorder {
+    fn record_span(&self, span: SpanData) {
+        self.spans
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(span);
+    }
+}
+
+impl From<CapturingRecorder> for SpanRecorder {
+    fn from(value: CapturingRecorder) -> Self {
+        Self::Dyn(Box::new(value))
+    }
+}
+
+/// Run `f` with a temporary, thread-scoped chronograph standing in for [crate::with_global] and the thread-local spans, returning every span it recorded.
+///
+/// The once-initialized global chronograph is never touched, so tests running in parallel don't interfere with each other.
+/// Any thread-local span left open by `f` is ended, and the caller's thread-local span is restored afterwards, along with its parents.
+pub fn with_capture(f: impl FnOnce()) -> Vec<SpanData> {
+    let recorder = CapturingRecorder::default();
+    let chronograph = Chronograph::builder()
+        .with_recorder(recorder.clone())
+        .build();
+    /// Ends the spans left open by `f` and restores the caller's spans, even if `f` panics
+    struct Restore(Vec<Span>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            local::end_threadlocal_spans(local::exit_threadlocal_spans(0));
+            local::enter_threadlocal_spans(take(&mut self.0));
+        }
+    }
+    let restore = Restore(local::exit_threadlocal_spans(0));
+    with_scoped_global(&chronograph, || {
+        f();
+        local::end_threadlocal_spans(local::exit_threadlocal_spans(0));
+    });
+    drop(restore);
+    recorder.take()
+}
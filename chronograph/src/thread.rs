@@ -0,0 +1,47 @@
+//! Identification of the thread a span was started on, see [crate::ChronographBuilder::with_thread_info].
+
+use std::cell::Cell;
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{registry, schema::ThreadInfo};
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+static NEXT_THREAD_ID: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    static CURRENT: Cell<Option<ThreadInfo>> = const { Cell::new(None) };
+}
+
+/// The current thread, resolved once per thread so its name is only registered the first time
+pub(crate) fn current_thread() -> ThreadInfo {
+    CURRENT
+        .try_with(|current| match current.get() {
+            Some(thread) => thread,
+            None => {
+                let thread = resolve_current_thread();
+                current.set(Some(thread));
+                thread
+            }
+        })
+        // the thread-local is gone while the thread exits, so resolve it again
+        .unwrap_or_else(|_| resolve_current_thread())
+}
+
+fn resolve_current_thread() -> ThreadInfo {
+    ThreadInfo {
+        id: os_thread_id(),
+        name: std::thread::current().name().map(registry::register_label),
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn os_thread_id() -> u64 {
+    // Safety: gettid takes no arguments and always succeeds
+    unsafe { libc::syscall(libc::SYS_gettid) as u64 }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub(crate) fn os_thread_id() -> u64 {
+    NEXT_THREAD_ID.fetch_add(1, Ordering::Relaxed)
+}
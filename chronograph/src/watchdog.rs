@@ -0,0 +1,187 @@
+//! Detection of leaked spans, see [crate::ChronographBuilder::with_watchdog].
+//!
+//! Every sampled span is registered with the watchdog when it starts. A watchdog thread periodically finalizes
+//! spans that have been alive longer than the timeout, recording them with a "timed_out" datapoint,
+//! and the span itself is no longer recorded if it ends later.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, Weak,
+    },
+    time::Duration,
+};
+
+use crate::{
+    schema::{DatapointId, RecordData, RecordValue, SpanData},
+    ChronographContext,
+};
+
+/// Datapoint marking a span finalized by the watchdog
+pub(crate) const TIMED_OUT: &str = "timed_out";
+
+/// The registry of live spans, owned by a chronograph context
+#[derive(Debug)]
+pub(crate) struct Watchdog {
+    /// In nanoseconds
+    timeout: u64,
+    spans: Mutex<Vec<Arc<WatchedSpan>>>,
+}
+
+/// What the watchdog needs to record a span on its behalf
+#[derive(Debug)]
+pub(crate) struct WatchedSpan {
+    span_id: u64,
+    parent_span_id: Option<u64>,
+    trace_id: u128,
+    start_unix_time: i64,
+    start_instant: u64,
+    name: Mutex<Option<DatapointId>>,
+    /// Set by whichever of the span and the watchdog finalizes it first
+    finalized: AtomicBool,
+}
+
+/// A span's registration with the [Watchdog], which is not carried over to clones of the span
+#[derive(Debug, Default)]
+pub(crate) struct WatchHandle(Option<Arc<WatchedSpan>>);
+
+impl Clone for WatchHandle {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl Watchdog {
+    pub(crate) fn new(timeout: Duration) -> Self {
+        Self {
+            timeout: timeout.as_nanos() as u64,
+            spans: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub(crate) fn timeout(&self) -> Duration {
+        Duration::from_nanos(self.timeout)
+    }
+
+    pub(crate) fn watch(
+        &self,
+        span_id: u64,
+        parent_span_id: Option<u64>,
+        trace_id: u128,
+        start_unix_time: i64,
+        start_instant: u64,
+    ) -> WatchHandle {
+        let span = Arc::new(WatchedSpan {
+            span_id,
+            parent_span_id,
+            trace_id,
+            start_unix_time,
+            start_instant,
+            name: Mutex::new(None),
+            finalized: AtomicBool::new(false),
+        });
+        self.spans
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(Arc::clone(&span));
+        WatchHandle(Some(span))
+    }
+
+    /// The id, name and start instant of every span being watched that has not been finalized yet
+    #[cfg(feature = "introspect")]
+    pub(crate) fn watched(&self) -> Vec<(u64, Option<DatapointId>, u64)> {
+        self.spans
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .filter(|span| !span.finalized.load(Ordering::Relaxed))
+            .map(|span| {
+                (
+                    span.span_id,
+                    *span.name.lock().unwrap_or_else(|e| e.into_inner()),
+                    span.start_instant,
+                )
+            })
+            .collect()
+    }
+
+    /// Spawn the watchdog thread, which only holds a weak reference so it never keeps the context alive
+    pub(crate) fn spawn(&self, context: Weak<ChronographContext>) {
+        let timeout = self.timeout();
+        // check often enough that a span is never finalized much later than its timeout
+        let interval = (timeout / 4).max(Duration::from_millis(1));
+        std::thread::Builder::new()
+            .name("chronograph watchdog".to_owned())
+            .spawn(move || loop {
+                std::thread::sleep(interval);
+                let Some(context) = context.upgrade() else {
+                    return;
+                };
+                if let Some(watchdog) = &context.watchdog {
+                    for span in watchdog.take_timed_out(context.clock.elapsed_nanos()) {
+                        context.record_span(span);
+                    }
+                }
+            })
+            .expect("could not spawn std thread");
+    }
+
+    /// Finalize spans alive longer than the timeout, forgetting spans that have ended
+    fn take_timed_out(&self, now: u64) -> Vec<SpanData> {
+        let mut timed_out = Vec::new();
+        self.spans
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|span| {
+                if span.finalized.load(Ordering::Relaxed) {
+                    return false;
+                }
+                if now.saturating_sub(span.start_instant) < self.timeout {
+                    return true;
+                }
+                if !span.finalized.swap(true, Ordering::AcqRel) {
+                    timed_out.push(span.span_data(now));
+                }
+                false
+            });
+        timed_out
+    }
+}
+
+impl WatchedSpan {
+    fn span_data(&self, end_instant: u64) -> SpanData {
+        SpanData {
+            span_id: self.span_id,
+            parent_span_id: self.parent_span_id,
+            trace_id: self.trace_id,
+            name: *self.name.lock().unwrap_or_else(|e| e.into_inner()),
+            start_unix_time: self.start_unix_time,
+            start_instant: self.start_instant,
+            end_instant,
+            records: vec![RecordData {
+                datapoint_id: DatapointId::from(TIMED_OUT),
+                value: RecordValue::Bool(true),
+                unit: None,
+                location: None,
+                instant: None,
+            }],
+            ..Default::default()
+        }
+    }
+}
+
+impl WatchHandle {
+    pub(crate) fn set_name(&self, name: DatapointId) {
+        if let Some(span) = &self.0 {
+            *span.name.lock().unwrap_or_else(|e| e.into_inner()) = Some(name);
+        }
+    }
+
+    /// Claim the span for the caller to finalize, returning false when the watchdog already finalized it
+    pub(crate) fn finalize(&mut self) -> bool {
+        match self.0.take() {
+            Some(span) => !span.finalized.swap(true, Ordering::AcqRel),
+            None => true,
+        }
+    }
+}